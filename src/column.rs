@@ -0,0 +1,103 @@
+use std::fmt;
+use std::ops::{Range, RangeFrom, RangeInclusive};
+
+use crate::error::CsvError;
+
+/// Refers to a column by its 0-based position or by its header name, or to
+/// a run of columns by a half-open range of positions.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+    /// Columns `start..end`. An `end` of `usize::MAX`, as produced from a
+    /// `start..` range, stops at the last column.
+    Range(Range<usize>),
+}
+
+impl Column {
+    /// Positions this column stands for in rows described by `headers`, for
+    /// inputs read as a stream whose width is not known up front. Indexes
+    /// are taken as they are; names and open ranges need `headers`.
+    pub(crate) fn resolve(&self, headers: Option<&[String]>) -> Result<Vec<usize>, CsvError> {
+        let unknown = || CsvError::UnknownColumn {
+            column: self.to_string(),
+        };
+        match self {
+            Column::Index(index) => Ok(vec![*index]),
+            Column::Name(name) => headers
+                .and_then(|headers| headers.iter().position(|h| h == name))
+                .map(|index| vec![index])
+                .ok_or_else(unknown),
+            Column::Range(range) if range.end == usize::MAX => match headers {
+                Some(headers) if range.start < headers.len() => {
+                    Ok((range.start..headers.len()).collect())
+                }
+                _ => Err(unknown()),
+            },
+            Column::Range(range) => Ok(range.clone().collect()),
+        }
+    }
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Self {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Self {
+        Column::Name(name.to_string())
+    }
+}
+
+impl From<String> for Column {
+    fn from(name: String) -> Self {
+        Column::Name(name)
+    }
+}
+
+impl From<Range<usize>> for Column {
+    fn from(range: Range<usize>) -> Self {
+        Column::Range(range)
+    }
+}
+
+impl From<RangeInclusive<usize>> for Column {
+    fn from(range: RangeInclusive<usize>) -> Self {
+        // `start..=usize::MAX` is every column from `start` on, which is
+        // what an `end` of `usize::MAX` already stands for.
+        Column::Range(*range.start()..range.end().saturating_add(1))
+    }
+}
+
+impl From<RangeFrom<usize>> for Column {
+    fn from(range: RangeFrom<usize>) -> Self {
+        Column::Range(range.start..usize::MAX)
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Index(index) => write!(f, "#{}", index),
+            Column::Name(name) => write!(f, "{:?}", name),
+            Column::Range(range) if range.end == usize::MAX => write!(f, "#{}..", range.start),
+            Column::Range(range) => write!(f, "#{}..{}", range.start, range.end),
+        }
+    }
+}
+
+/// What to do with rows whose key occurs in both tables of a keyed set
+/// operation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ConflictPolicy {
+    /// Keep the rows from the left table.
+    #[default]
+    KeepLeft,
+    /// Keep the rows from the right table.
+    KeepRight,
+    /// Fail with [`CsvError::KeyConflict`](crate::CsvError::KeyConflict)
+    /// unless the rows on both sides are identical.
+    Error,
+}
@@ -1,258 +1,491 @@
 use std::cmp::{max, min};
-use std::collections::btree_map::BTreeMap;
 use std::collections::HashMap;
-use std::error::Error;
-use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
+
+use crate::column::Column;
+use crate::dialect::Dialect;
+use crate::error::CsvError;
+use crate::reader::ReaderBuilder;
+use crate::setops::{LineCounts, Semantics, SetOptions};
+use crate::tokenizer::parse_records;
+use crate::writer::{CsvWriter, QuoteStyle, WriterBuilder};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CsvData {
-    data: Vec<String>,
-    delimiter: char,
-    line_width: usize,
+    pub(crate) data: Vec<String>,
+    pub(crate) dialect: Dialect,
+    pub(crate) line_width: usize,
+    pub(crate) headers: Option<Vec<String>>,
 }
 
 impl CsvData {
-    pub fn new(data: Vec<String>, delimiter: char, line_width: usize) -> Self {
-        CsvData {
+    /// A table of the cells in `data`, `line_width` to a row. Fails unless
+    /// the cells fill every row.
+    pub fn new(data: Vec<String>, delimiter: char, line_width: usize) -> Result<Self, CsvError> {
+        let short = match line_width {
+            0 => data.len(),
+            _ => data.len() % line_width,
+        };
+        if short > 0 {
+            return Err(CsvError::RaggedRow {
+                line: data.len().checked_div(line_width).unwrap_or(0) + 1,
+                expected: line_width,
+                found: short,
+            });
+        }
+        Ok(CsvData {
             data,
-            delimiter,
+            dialect: Dialect::new(delimiter),
             line_width,
-        }
+            headers: None,
+        })
     }
 
-    pub fn from_raw_string(data: String, delimiter: char, line_width: usize) -> Self {
+    pub fn from_raw_string(
+        data: String,
+        delimiter: char,
+        line_width: usize,
+    ) -> Result<Self, CsvError> {
         if data.is_empty() {
-            return CsvData {
+            return Ok(CsvData {
                 data: Vec::new(),
-                delimiter,
+                dialect: Dialect::new(delimiter),
                 line_width,
-            };
+                headers: None,
+            });
         }
-        let mut vec: Vec<String> = data
-            .split(delimiter)
+        let mut vec: Vec<String> = parse_records(&data, delimiter)?
             .into_iter()
-            .map(|s| s.to_string())
+            .flatten()
             .collect();
 
-        for _ in 0..vec.len() % line_width {
-            vec.push("".to_string());
+        if line_width == 0 {
+            return Err(CsvError::RaggedRow {
+                line: 1,
+                expected: 0,
+                found: vec.len(),
+            });
+        }
+        let short = vec.len() % line_width;
+        if short > 0 {
+            vec.resize(vec.len() + line_width - short, "".to_string());
         }
 
-        CsvData {
+        Ok(CsvData {
             data: vec,
-            delimiter,
+            dialect: Dialect::new(delimiter),
             line_width,
+            headers: None,
+        })
+    }
+    pub fn from_text(data: String, delimiter: char) -> Result<Self, CsvError> {
+        CsvData::from_records(parse_records(&data, delimiter)?, Dialect::new(delimiter))
+    }
+
+    /// Like [`CsvData::from_text`], taking the first line as the header row.
+    pub fn from_text_with_headers(data: String, delimiter: char) -> Result<Self, CsvError> {
+        let dialect = Dialect {
+            has_headers: true,
+            ..Dialect::new(delimiter)
+        };
+        CsvData::from_records(parse_records(&data, delimiter)?, dialect)
+    }
+
+    pub fn to_file(&self, file_name: String) -> Result<(), CsvError> {
+        self.to_file_with_quoting(file_name, QuoteStyle::Minimal)
+    }
+
+    pub fn to_file_with_quoting(
+        &self,
+        file_name: String,
+        quote_style: QuoteStyle,
+    ) -> Result<(), CsvError> {
+        let file = File::create(file_name)?;
+        self.to_writer(
+            WriterBuilder::new()
+                .dialect(self.dialect)
+                .quote_style(quote_style)
+                .build(file),
+        )
+    }
+
+    /// Writes the header row, if any, and every data row through `writer`,
+    /// then flushes it.
+    pub fn to_writer<W: Write>(&self, mut writer: CsvWriter<W>) -> Result<(), CsvError> {
+        if let Some(headers) = &self.headers {
+            writer.write_record(headers)?;
         }
+        for line in self {
+            writer.write_record(&line)?;
+        }
+        writer.flush()
+    }
+
+    pub fn from_file<S: AsRef<str>>(filename: S, delimiter: char) -> Result<Self, CsvError> {
+        CsvData::from_file_with_headers(filename, delimiter, false)
     }
-    pub fn from_text(data: String, delimiter: char) -> Self {
-        let lines: Vec<String> = data
-            .split('\n')
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect();
 
-        let line_width = lines
+    /// Reads a file, taking its first line as the header row when
+    /// `has_headers` is set. Headers are kept apart from the data, so
+    /// iteration and the set operations only ever see data rows. The
+    /// encoding of the file is detected, see [`detect_encoding`](crate::detect_encoding).
+    pub fn from_file_with_headers<S: AsRef<str>>(
+        filename: S,
+        delimiter: char,
+        has_headers: bool,
+    ) -> Result<Self, CsvError> {
+        ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(has_headers)
+            .read_path(filename.as_ref())
+    }
+
+    /// Reads a whole table from any [`Read`] source such as stdin, skipping
+    /// blank lines like [`CsvData::from_file`].
+    pub fn from_reader<R: Read>(
+        reader: R,
+        delimiter: char,
+        has_headers: bool,
+    ) -> Result<Self, CsvError> {
+        ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(has_headers)
+            .read(reader)
+    }
+
+    /// Builds a table from parsed records, taking the first one as the
+    /// header row and trimming cells as `dialect` says.
+    pub(crate) fn from_records(
+        mut records: Vec<Vec<String>>,
+        dialect: Dialect,
+    ) -> Result<Self, CsvError> {
+        if records.is_empty() {
+            return Err(CsvError::EmptyInput);
+        }
+        let headers = if dialect.has_headers {
+            Some(records.remove(0))
+        } else {
+            None
+        };
+        let headers = headers.map(|headers| trim_line(headers, dialect.trim.headers()));
+        if dialect.trim.fields() {
+            records = records
+                .into_iter()
+                .map(|record| trim_line(record, true))
+                .collect();
+        }
+        let line_width = records
             .iter()
-            .map(|s| s.split(delimiter).count())
+            .chain(headers.iter())
+            .map(|r| r.len())
             .max()
-            .unwrap();
+            .unwrap_or(0);
 
-        let result_data: Vec<String> = lines
+        let data: Vec<String> = records
             .into_iter()
-            .flat_map(|s| {
-                let mut curr_line = s
-                    .split(delimiter)
-                    .into_iter()
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>();
-
-                for _ in 0..(line_width - curr_line.len()) {
-                    curr_line.push("".to_string());
-                }
-                curr_line
+            .flat_map(|mut record| {
+                record.resize(line_width, "".to_string());
+                record
             })
             .collect();
 
-        CsvData {
-            data: result_data,
-            delimiter,
+        Ok(CsvData {
+            data,
+            dialect,
             line_width,
+            headers: headers.map(|headers| pad_line(headers, line_width)),
+        })
+    }
+
+    pub fn headers(&self) -> Option<&[String]> {
+        self.headers.as_deref()
+    }
+
+    /// Returns a copy of this table with `headers` as its header row, padding
+    /// either side so that both share the same width.
+    pub fn with_headers(&self, headers: Vec<String>) -> CsvData {
+        let width = max(self.line_width, headers.len());
+        CsvData {
+            headers: Some(pad_line(headers, width)),
+            ..self.widen(width)
         }
     }
-    pub fn to_file(&self, file_name: String) -> std::io::Result<()> {
-        let mut file = File::create(file_name)?;
 
-        let vec_buf: Vec<u8> = self
-            .into_iter()
-            .map(|s| s.join(self.delimiter.to_string().as_ref()) + "\n")
-            .map(|s| s.into_bytes())
-            .flatten()
-            .collect();
+    pub fn delimiter(&self) -> char {
+        self.dialect.delimiter
+    }
 
-        let buf: &[u8] = &vec_buf;
-
-        file.write_all(buf)
-    }
-
-    pub fn from_file<S: AsRef<str>>(filename: S, delimiter: char) -> Result<Self, Box<dyn Error>> {
-        match fs::read_to_string(filename.as_ref()) {
-            Ok(file) => {
-                let lines: Vec<String> = file
-                    .split('\n')
-                    .map(|s| s.to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-                let line_width = lines
-                    .iter()
-                    .map(|s| s.matches(delimiter).count() + 1)
-                    .max()
-                    .unwrap();
-                let csv_data = lines
-                    .iter()
-                    .flat_map(|v| {
-                        let mut split: Vec<&str> = v.split(delimiter).collect();
-                        for _ in 0..(line_width - split.len()) {
-                            split.push("");
-                        }
-                        split
-                    })
-                    .map(|s| s.to_string())
-                    .collect();
-
-                Ok(CsvData {
-                    data: csv_data,
-                    delimiter,
-                    line_width,
-                })
-            }
+    /// The dialect this table was read with and is written in. Its header
+    /// flag tells whether the table has a header row.
+    pub fn dialect(&self) -> Dialect {
+        Dialect {
+            has_headers: self.headers.is_some(),
+            ..self.dialect
+        }
+    }
 
-            Err(e) => Err(Box::new(e)),
+    /// Returns a copy of this table written with `delimiter`, so that it can
+    /// be combined with tables read with another one.
+    pub fn with_delimiter(&self, delimiter: char) -> CsvData {
+        self.with_dialect(Dialect {
+            delimiter,
+            ..self.dialect
+        })
+    }
+
+    /// Returns a copy of this table written in `dialect`, so that it can be
+    /// combined with tables in that dialect. The header row is kept as it is,
+    /// whatever the header flag of `dialect`.
+    pub fn with_dialect(&self, dialect: Dialect) -> CsvData {
+        CsvData {
+            dialect,
+            ..self.clone()
         }
     }
 
-    pub fn union(&self, second: &CsvData) -> Option<CsvData> {
-        if self.delimiter != second.delimiter {
-            return None;
+    /// Position of the first column whose header is `name`.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.headers.as_ref()?.iter().position(|h| h == name)
+    }
+
+    /// Resolves every column to its position, expanding ranges, and fails
+    /// with [`CsvError::UnknownColumn`] for a missing name or an index past
+    /// the last column.
+    pub fn column_indexes(&self, columns: &[Column]) -> Result<Vec<usize>, CsvError> {
+        let mut indexes = Vec::new();
+        for column in columns {
+            let unknown = || CsvError::UnknownColumn {
+                column: column.to_string(),
+            };
+            match column {
+                Column::Index(index) if *index < self.line_width => indexes.push(*index),
+                Column::Name(name) => indexes.push(self.column_index(name).ok_or_else(unknown)?),
+                Column::Range(range)
+                    if range.end == usize::MAX && range.start < self.line_width =>
+                {
+                    indexes.extend(range.start..self.line_width)
+                }
+                Column::Range(range) if range.end <= self.line_width => {
+                    indexes.extend(range.clone())
+                }
+                _ => return Err(unknown()),
+            }
         }
+        Ok(indexes)
+    }
+
+    /// The data rows as slices, without copying them.
+    pub(crate) fn rows(&self) -> std::slice::Chunks<'_, String> {
+        self.data.chunks(self.line_width.max(1))
+    }
+
+    pub fn union(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.union_with(second, &SetOptions::default())
+    }
+
+    pub fn union_with(&self, second: &CsvData, options: &SetOptions) -> Result<CsvData, CsvError> {
+        check_dialects(self, second)?;
 
         let width = max(self.line_width, second.line_width);
-        let mut lines_map: BTreeMap<String, i32> = self.lines_map_from_csv(width);
+        let mut lines_map = self.lines_map_from_csv(width);
 
-        second.into_iter().for_each(|v| {
-            let mut line = v.join(&self.delimiter.to_string());
-            let abs = (v.len() as i32 - width as i32).abs();
+        second.into_iter().for_each(|mut line| {
+            let abs = (line.len() as i32 - width as i32).abs();
 
             for _ in 0..abs {
-                line += ", ";
+                line.push(" ".to_string());
             }
 
-            *lines_map.entry(line).or_insert(0) += 1;
+            lines_map.add(line, 1);
         });
 
-        let mut lines = Vec::new();
-        lines_map.iter().for_each(|(k, v): (&String, &i32)| {
-            for _ in 0..*v {
-                lines.push(k.to_owned());
-            }
-        });
-        let result_data = lines
+        let result_data = lines_map
+            .into_ordered(options.order)
             .into_iter()
-            .flat_map(|v| {
-                v.split(self.delimiter)
-                    .map(|s| s.to_owned())
-                    .collect::<Vec<String>>()
-            })
+            .flat_map(|(line, v)| repeat_line(line, options.copies(v)))
             .collect::<Vec<String>>();
 
-        Some(CsvData {
+        Ok(CsvData {
             data: result_data,
-            delimiter: self.delimiter,
+            dialect: self.dialect,
             line_width: width,
+            headers: self.merged_headers(second, width)?,
         })
     }
 
-    pub fn intersection(&self, second: &CsvData) -> Option<CsvData> {
-        if self.delimiter != second.delimiter {
-            return None;
-        }
+    pub fn intersection(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.intersection_with(second, &SetOptions::default())
+    }
+
+    pub fn intersection_with(
+        &self,
+        second: &CsvData,
+        options: &SetOptions,
+    ) -> Result<CsvData, CsvError> {
+        check_dialects(self, second)?;
 
         let width = max(self.line_width, second.line_width);
-        let self_lines_map: BTreeMap<String, i32> = self.lines_map_from_csv(width);
-        let second_lines_map: BTreeMap<String, i32> = second.lines_map_from_csv(width);
+        let self_lines_map = self.lines_map_from_csv(width);
+        let second_lines_map = second.lines_map_from_csv(width);
 
         let result_data = self_lines_map
-            .iter()
-            .filter(|(line, &_v)| second_lines_map.contains_key(*line))
-            .flat_map(|(line, &v)| {
-                let num_lines = min(v, *second_lines_map.get(line).unwrap());
-                let mut data = Vec::new();
-                for _ in 0..num_lines {
-                    data.push(
-                        line.split(self.delimiter)
-                            .map(|s| s.to_owned())
-                            .collect::<Vec<String>>(),
-                    );
-                }
-                data
+            .into_ordered(options.order)
+            .into_iter()
+            .filter_map(|(line, v)| {
+                let num_lines = min(v, second_lines_map.get(&line)?);
+                Some(repeat_line(line, options.copies(num_lines)))
             })
             .flatten()
             .collect();
 
-        Some(CsvData {
+        Ok(CsvData {
             data: result_data,
-            delimiter: self.delimiter,
+            dialect: self.dialect,
             line_width: width,
+            headers: self.merged_headers(second, width)?,
         })
     }
 
-    pub fn difference(&self, second: &CsvData) -> Option<CsvData> {
-        if self.delimiter != second.delimiter {
-            return None;
-        }
+    /// Same as [`symmetric_difference`](CsvData::symmetric_difference).
+    pub fn difference(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.symmetric_difference(second)
+    }
+
+    /// Same as [`symmetric_difference_with`](CsvData::symmetric_difference_with).
+    pub fn difference_with(
+        &self,
+        second: &CsvData,
+        options: &SetOptions,
+    ) -> Result<CsvData, CsvError> {
+        self.symmetric_difference_with(second, options)
+    }
+
+    pub fn except(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.except_with(second, &SetOptions::default())
+    }
+
+    /// Same as [`except`](CsvData::except).
+    pub fn minus(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.except(second)
+    }
+
+    /// Rows of `self` that `second` does not account for. With bag semantics
+    /// this is SQL `EXCEPT ALL`: a row held three times by `self` and once by
+    /// `second` is kept twice. With distinct semantics it is SQL `EXCEPT`:
+    /// rows of `self` that `second` does not hold at all, once each.
+    pub fn except_with(&self, second: &CsvData, options: &SetOptions) -> Result<CsvData, CsvError> {
+        check_dialects(self, second)?;
+
+        let width = max(self.line_width, second.line_width);
+        let second_lines_map = second.lines_map_from_csv(width);
+        let result_data =
+            lines_map_to_except(self.lines_map_from_csv(width), &second_lines_map, options);
+
+        Ok(CsvData {
+            data: result_data,
+            dialect: self.dialect,
+            line_width: width,
+            headers: self.merged_headers(second, width)?,
+        })
+    }
+
+    pub fn symmetric_difference(&self, second: &CsvData) -> Result<CsvData, CsvError> {
+        self.symmetric_difference_with(second, &SetOptions::default())
+    }
+
+    /// `self` except `second` followed by `second` except `self`. With bag
+    /// semantics a row held more often by one table is kept as many times as
+    /// that table holds it in surplus.
+    pub fn symmetric_difference_with(
+        &self,
+        second: &CsvData,
+        options: &SetOptions,
+    ) -> Result<CsvData, CsvError> {
+        check_dialects(self, second)?;
 
         let width = max(self.line_width, second.line_width);
-        let self_lines_map: BTreeMap<String, i32> = self.lines_map_from_csv(width);
-        let second_lines_map: BTreeMap<String, i32> = second.lines_map_from_csv(width);
+        let self_lines_map = self.lines_map_from_csv(width);
+        let second_lines_map = second.lines_map_from_csv(width);
 
         let result_data_first =
-            lines_map_to_difference(&self_lines_map, &second_lines_map, &self.delimiter);
-        let result_data_second =
-            lines_map_to_difference(&second_lines_map, &self_lines_map, &self.delimiter);
+            lines_map_to_except(self_lines_map.clone(), &second_lines_map, options);
+        let result_data_second = lines_map_to_except(second_lines_map, &self_lines_map, options);
 
         let mut result_data = Vec::new();
         result_data.extend(result_data_first);
         result_data.extend(result_data_second);
-        Some(CsvData {
+        Ok(CsvData {
             data: result_data,
-            delimiter: self.delimiter,
+            dialect: self.dialect,
             line_width: width,
+            headers: self.merged_headers(second, width)?,
         })
     }
 
-    fn lines_map_from_csv(&self, width: usize) -> BTreeMap<String, i32> {
-        self.into_iter().fold(BTreeMap::new(), |mut acc, v| {
-            let mut line = v.join(&self.delimiter.to_string());
-            let abs = (v.len() as i32 - width as i32).abs();
-            for _ in 0..abs {
-                line += ",";
-            }
+    /// Copy of this table with every row and the header padded with empty
+    /// cells to `line_width`. Rows that are already wider are left as is.
+    pub(crate) fn widen(&self, line_width: usize) -> CsvData {
+        let mut new_data = Vec::new();
+        self.into_iter()
+            .for_each(|data| new_data.extend(pad_line(data, line_width)));
+        CsvData {
+            data: new_data,
+            dialect: self.dialect,
+            line_width,
+            headers: self
+                .headers
+                .clone()
+                .map(|headers| pad_line(headers, line_width)),
+        }
+    }
 
-            *acc.entry(line).or_insert(0) += 1;
-            acc
-        })
+    /// The header row of whichever table has one, padded to `width`. Fails
+    /// if both have one and they differ.
+    pub(crate) fn merged_headers(
+        &self,
+        second: &CsvData,
+        width: usize,
+    ) -> Result<Option<Vec<String>>, CsvError> {
+        if let (Some(left), Some(right)) = (&self.headers, &second.headers) {
+            check_headers(left, right, width)?;
+        }
+        Ok(self
+            .headers
+            .clone()
+            .or_else(|| second.headers.clone())
+            .map(|headers| pad_line(headers, width)))
     }
 
+    fn lines_map_from_csv(&self, width: usize) -> LineCounts {
+        self.into_iter()
+            .fold(LineCounts::new(), |mut acc, mut line| {
+                line.resize(width, "".to_string());
+
+                acc.add(line, 1);
+                acc
+            })
+    }
+
+    /// Flips rows and columns. A header row takes part in the transposition
+    /// as the first row, so the result has no headers.
     pub fn transpose(&self) -> CsvData {
-        let data = self.data.clone();
+        if self.line_width == 0 {
+            return CsvData {
+                data: Vec::new(),
+                headers: None,
+                ..self.clone()
+            };
+        }
+        let mut data = self.headers.clone().unwrap_or_default();
+        data.extend(self.data.iter().cloned());
         let num_lines = data.len() / self.line_width;
         let mut matrix = vec![vec![""; num_lines]; self.line_width];
 
-        for i in 0..data.len() {
+        for (i, cell) in data.iter().enumerate() {
             let (x, y) = (i / self.line_width, i % self.line_width);
 
-            matrix[y][x] = &*data[i];
+            matrix[y][x] = cell;
         }
 
         let result_data: Vec<String> = matrix
@@ -262,136 +495,246 @@ impl CsvData {
         CsvData {
             data: result_data,
             line_width: num_lines,
-            delimiter: self.delimiter,
+            dialect: self.dialect,
+            headers: None,
         }
     }
 }
 
-fn lines_map_to_difference(
-    map1: &BTreeMap<String, i32>,
-    map2: &BTreeMap<String, i32>,
-    delimiter: &char,
-) -> Vec<String> {
-    map1.iter()
-        .filter(|(line, &_v)| !map2.contains_key(*line))
-        .flat_map(|(line, &num_lines)| {
-            let mut data = Vec::new();
-            for _ in 0..num_lines {
-                data.push(
-                    line.split(*delimiter)
-                        .map(|s| s.to_owned())
-                        .collect::<Vec<String>>(),
-                );
-            }
-            data
+/// Rows of `map1` left over once the copies in `map2` are taken away, or
+/// with distinct semantics the rows `map2` does not hold at all.
+fn lines_map_to_except(map1: LineCounts, map2: &LineCounts, options: &SetOptions) -> Vec<String> {
+    map1.into_ordered(options.order)
+        .into_iter()
+        .flat_map(|(line, num_lines)| {
+            let others = map2.get(&line).unwrap_or(0);
+            let surplus = match options.semantics {
+                Semantics::Bag => num_lines - others,
+                Semantics::Distinct if others > 0 => 0,
+                Semantics::Distinct => num_lines,
+            };
+            repeat_line(line, options.copies(surplus))
         })
-        .flatten()
         .collect()
 }
 
-pub fn union_all(csvs: &[CsvData], delimiter: char, line_width: usize) -> CsvData {
+fn repeat_line(line: Vec<String>, times: i32) -> Vec<String> {
+    let mut data = Vec::new();
+    for _ in 0..times {
+        data.extend(line.iter().cloned());
+    }
+    data
+}
+
+/// Concatenates every table, written with `delimiter` and padded to at least
+/// `line_width` columns.
+pub fn union_all(
+    csvs: &[CsvData],
+    delimiter: char,
+    line_width: usize,
+) -> Result<CsvData, CsvError> {
     let mut result_data = Vec::new();
 
-    let mut width = csvs.iter().map(|csv| csv.line_width).max().unwrap();
+    let mut width = max_width(csvs)?;
+    check_all_dialects(csvs)?;
 
     width = max(line_width, width);
-    let csvs = pad(csvs, width);
+    let csvs = pad(csvs, width)?;
+    let headers = first_headers(&csvs)?;
+    let dialect = Dialect {
+        delimiter,
+        ..csvs[0].dialect
+    };
     csvs.into_iter()
         .for_each(|csv| csv.into_iter().for_each(|line| result_data.extend(line)));
 
-    CsvData {
+    Ok(CsvData {
         data: result_data,
-        delimiter,
-        line_width,
-    }
+        dialect,
+        line_width: width,
+        headers,
+    })
 }
 
-pub fn intersection_all(csvs: &[CsvData]) -> Option<CsvData> {
-    let mut width = csvs.iter().map(|csv| csv.line_width).max().unwrap();
-    let csvs = pad(csvs, width);
-    let mut csv_iterator = csvs.iter().cloned();
-    let first = csv_iterator.next().unwrap();
-
-    csv_iterator.try_fold(first, |item, other| {
-        let intersection = item.intersection(&other);
+/// Like [`union_all`], but with the rows ordered and, for
+/// [`Semantics::Distinct`](crate::Semantics::Distinct), deduplicated as
+/// `options` asks.
+pub fn union_all_with(
+    csvs: &[CsvData],
+    delimiter: char,
+    line_width: usize,
+    options: &SetOptions,
+) -> Result<CsvData, CsvError> {
+    let union = union_all(csvs, delimiter, line_width)?;
+    let mut lines_map = LineCounts::new();
+    (&union).into_iter().for_each(|line| lines_map.add(line, 1));
+
+    let result_data = lines_map
+        .into_ordered(options.order)
+        .into_iter()
+        .flat_map(|(line, v)| repeat_line(line, options.copies(v)))
+        .collect();
 
-        if let Some(result) = intersection {
-            if result.data.is_empty() {
-                return None;
-            }
-            return Some(result);
-        }
-        None
+    Ok(CsvData {
+        data: result_data,
+        ..union
     })
 }
 
-fn difference_all(csvs: &[CsvData]) -> CsvData {
-    let mut lines = BTreeMap::new();
-    let mut count_map = HashMap::new();
-    let width = csvs.iter().map(|csv| csv.line_width).max().unwrap();
-    let csvs = pad(csvs, width);
-    let length = csvs.len();
-    let delim = csvs[0].delimiter;
+/// Rows present in every table. An empty intersection is an empty table, not
+/// an error.
+pub fn intersection_all(csvs: &[CsvData]) -> Result<CsvData, CsvError> {
+    intersection_all_with(csvs, &SetOptions::default())
+}
+
+pub fn intersection_all_with(csvs: &[CsvData], options: &SetOptions) -> Result<CsvData, CsvError> {
+    let width = max_width(csvs)?;
+    let csvs = pad(csvs, width)?;
+    let (first, rest) = csvs.split_first().ok_or(CsvError::EmptyInput)?;
+    match rest {
+        // A table is its own intersection, ordered and counted like any other.
+        [] => first.intersection_with(first, options),
+        _ => rest.iter().try_fold(first.clone(), |item, other| {
+            item.intersection_with(other, options)
+        }),
+    }
+}
+
+/// Rows that occur in exactly one of the tables, repeated as often as they
+/// occur there.
+pub fn difference_all(csvs: &[CsvData]) -> Result<CsvData, CsvError> {
+    difference_all_with(csvs, &SetOptions::default())
+}
+
+pub fn difference_all_with(csvs: &[CsvData], options: &SetOptions) -> Result<CsvData, CsvError> {
+    let mut count_map = LineCounts::new();
+    // For every row, the last table it was seen in and how many tables hold it.
+    let mut tables: HashMap<Vec<String>, (usize, usize)> = HashMap::new();
+    let width = max_width(csvs)?;
+    check_all_dialects(csvs)?;
+    let csvs = pad(csvs, width)?;
+    let dialect = csvs[0].dialect;
+    let headers = first_headers(&csvs)?;
     csvs.into_iter().enumerate().for_each(|(i, csv)| {
         csv.into_iter().for_each(|line| {
-            lines
-                .entry(line.clone())
-                .or_insert_with(|| (0..length).into_iter().map(|_| "0").collect::<String>())
-                .replace_range(i..i + 1, "1");
-            *count_map.entry(line).or_insert(0) += 1;
+            let seen = tables.entry(line.clone()).or_insert((i, 0));
+            if seen.1 == 0 || seen.0 != i {
+                *seen = (i, seen.1 + 1);
+            }
+            count_map.add(line, 1);
         })
     });
 
-    let result = lines
-        .iter()
-        .filter_map(|(key, value)| match num_ones(value) {
-            true => {
-                let mut all_lines = Vec::new();
-                for _ in 0..*count_map.get(key).unwrap() as usize {
-                    all_lines.extend(key.clone());
-                }
-
-                Some(all_lines)
-            }
-            _ => None,
-        })
-        .flatten()
+    let result = count_map
+        .into_ordered(options.order)
+        .into_iter()
+        .filter(|(line, _)| tables[line].1 == 1)
+        .flat_map(|(line, count)| repeat_line(line, options.copies(count)))
         .collect();
 
-    CsvData {
+    Ok(CsvData {
         data: result,
-        delimiter: delim,
+        dialect,
         line_width: width,
-    }
+        headers,
+    })
 }
 
-fn pad(csvs: &[CsvData], line_width: usize) -> Vec<CsvData> {
+/// Pads every row of every table with empty cells up to `line_width`.
+/// Fails with [`CsvError::RaggedRow`] if a row is already wider than that.
+pub fn pad(csvs: &[CsvData], line_width: usize) -> Result<Vec<CsvData>, CsvError> {
     csvs.iter()
         .map(|csv| {
-            let mut new_data = Vec::new();
-            csv.into_iter().for_each(|data| {
-                let abs = line_width - data.len();
-                let mut res: Vec<String> = Vec::new();
-                for item in data.iter() {
-                    res.push(item.clone());
+            if let Some(headers) = &csv.headers {
+                if headers.len() > line_width {
+                    return Err(CsvError::RaggedRow {
+                        line: 1,
+                        expected: line_width,
+                        found: headers.len(),
+                    });
                 }
-                for _ in 0..abs {
-                    res.push("".to_string());
-                }
-
-                new_data.extend(res)
-            });
-            CsvData {
-                data: new_data,
-                delimiter: csv.delimiter,
-                line_width,
             }
+            // Every data row is as wide as the table itself.
+            if !csv.data.is_empty() && csv.line_width > line_width {
+                return Err(CsvError::RaggedRow {
+                    line: csv.headers.iter().count() + 1,
+                    expected: line_width,
+                    found: csv.line_width,
+                });
+            }
+            Ok(csv.widen(line_width))
         })
         .collect()
 }
 
-fn num_ones(str: &String) -> bool {
-    str.chars().filter(|c| c.to_string() == "1").count() == 1
+fn trim_line(line: Vec<String>, trim: bool) -> Vec<String> {
+    match trim {
+        true => line
+            .into_iter()
+            .map(|cell| cell.trim().to_string())
+            .collect(),
+        false => line,
+    }
+}
+
+fn pad_line(mut line: Vec<String>, line_width: usize) -> Vec<String> {
+    while line.len() < line_width {
+        line.push("".to_string());
+    }
+    line
+}
+
+fn max_width(csvs: &[CsvData]) -> Result<usize, CsvError> {
+    csvs.iter()
+        .map(|csv| csv.line_width)
+        .max()
+        .ok_or(CsvError::EmptyInput)
+}
+
+/// Fails unless both tables are written in the same dialect, settings that
+/// only matter when reading aside, so that combining them never silently changes how one is written.
+pub(crate) fn check_dialects(first: &CsvData, second: &CsvData) -> Result<(), CsvError> {
+    if first.dialect.delimiter != second.dialect.delimiter {
+        return Err(CsvError::DelimiterMismatch {
+            left: first.dialect.delimiter,
+            right: second.dialect.delimiter,
+        });
+    }
+    if !first.dialect.compatible(&second.dialect) {
+        return Err(CsvError::DialectMismatch {
+            left: first.dialect(),
+            right: second.dialect(),
+        });
+    }
+    Ok(())
+}
+
+fn check_all_dialects(csvs: &[CsvData]) -> Result<(), CsvError> {
+    csvs.windows(2)
+        .try_for_each(|pair| check_dialects(&pair[0], &pair[1]))
+}
+
+/// The header row of the first table that has one. Fails if another table
+/// has a different one.
+fn first_headers(csvs: &[CsvData]) -> Result<Option<Vec<String>>, CsvError> {
+    let mut headers = csvs.iter().filter_map(|csv| csv.headers.as_ref());
+    let first = headers.next();
+    if let Some(first) = first {
+        for other in headers {
+            check_headers(first, other, first.len().max(other.len()))?;
+        }
+    }
+    Ok(first.cloned())
+}
+
+fn check_headers(left: &[String], right: &[String], width: usize) -> Result<(), CsvError> {
+    if pad_line(left.to_vec(), width) != pad_line(right.to_vec(), width) {
+        return Err(CsvError::HeaderMismatch {
+            left: left.to_vec(),
+            right: right.to_vec(),
+        });
+    }
+    Ok(())
 }
 
 impl IntoIterator for CsvData {
@@ -466,27 +809,35 @@ impl<'a> Iterator for CsvDataIterator<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::csvdata::{difference_all, intersection_all, pad, union_all, CsvData};
+    use crate::csvdata::{
+        difference_all, difference_all_with, intersection_all, intersection_all_with, pad,
+        union_all, union_all_with, CsvData,
+    };
+    use crate::dialect::{Dialect, LineTerminator, Trim};
+    use crate::error::CsvError;
+    use crate::reader::ReaderBuilder;
+    use crate::setops::{RowOrder, Semantics, SetOptions};
+    use crate::writer::{CsvWriter, QuoteStyle};
     use std::fs;
 
     #[test]
     fn test_from_str() {
         let expect = vec!["test", "test2", "test3"];
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 1);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 1).unwrap();
         assert_eq!(tmp.data, expect);
     }
 
     #[test]
     fn test_from_str_extra() {
         let expect = vec!["test", "test2", "test3", "", "", "", ""];
-        let tmp = CsvData::from_raw_string("test,test2,test3,,,,".to_string(), ',', 1);
+        let tmp = CsvData::from_raw_string("test,test2,test3,,,,".to_string(), ',', 1).unwrap();
         assert_eq!(tmp.data, expect);
     }
 
     #[test]
     fn test_from_str_fail() {
         let expect = vec!["test", "te2", "test3"];
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 1);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 1).unwrap();
         assert_ne!(tmp.data, expect);
     }
 
@@ -496,12 +847,13 @@ mod tests {
         let expected_str = expected_str.into_iter().map(|s| s.to_string()).collect();
         let expect = CsvData {
             data: expected_str,
-            delimiter: ',',
+            dialect: Dialect::default(),
             line_width: 3,
+            headers: None,
         };
 
         let text = "test\ntest1,test2,test3\ntest3".to_string();
-        let tmp = CsvData::from_text(text, ',');
+        let tmp = CsvData::from_text(text, ',').unwrap();
         assert_eq!(tmp, expect);
     }
 
@@ -513,12 +865,13 @@ mod tests {
         let expected_str = expected_str.into_iter().map(|s| s.to_string()).collect();
         let expect = CsvData {
             data: expected_str,
-            delimiter: ',',
+            dialect: Dialect::default(),
             line_width: 3,
+            headers: None,
         };
 
         let text = "test,test2,test3\ntest1,test2,test3\ntest3,test4,test5".to_string();
-        let tmp = CsvData::from_text(text, ',');
+        let tmp = CsvData::from_text(text, ',').unwrap();
         assert_eq!(tmp, expect);
     }
 
@@ -528,64 +881,102 @@ mod tests {
         let expected_str = expected_str.into_iter().map(|s| s.to_string()).collect();
         let expect = CsvData {
             data: expected_str,
-            delimiter: ',',
+            dialect: Dialect::default(),
             line_width: 1,
+            headers: None,
         };
 
         let text = "".to_string();
-        let tmp = CsvData::from_text(text, ',');
+        let tmp = CsvData::from_text(text, ',').unwrap();
         assert_eq!(tmp, expect);
     }
 
+    #[test]
+    fn test_from_text_quoted() {
+        let expected_str = vec!["Smith, John", "say \"hi\"", "multi\nline", ""];
+        let expected_str = expected_str.into_iter().map(|s| s.to_string()).collect();
+        let expect = CsvData {
+            data: expected_str,
+            dialect: Dialect::default(),
+            line_width: 2,
+            headers: None,
+        };
+
+        let text = "\"Smith, John\",\"say \"\"hi\"\"\"\n\"multi\nline\"".to_string();
+        let tmp = CsvData::from_text(text, ',').unwrap();
+        assert_eq!(tmp, expect);
+    }
+
+    #[test]
+    fn test_from_file_quoted() {
+        fs::write("testdata/testquoted.csv", "\"a,b\",c\n\"d\ne\",f\n").unwrap();
+        let result = CsvData::from_file("testdata/testquoted.csv", ',').unwrap();
+        let expect = CsvData::new(
+            vec!["a,b", "c", "d\ne", "f"]
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ',',
+            2,
+        )
+        .unwrap();
+        assert_eq!(result, expect);
+    }
+
+    #[test]
+    fn test_union_quoted() {
+        let tmp = CsvData::from_text("\"a,b\",c".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("d,e".to_string(), ',').unwrap();
+        let result = tmp.union(&tmp2).unwrap();
+        assert_eq!(result.data, vec!["a,b", "c", "d", "e"]);
+    }
+
     #[test]
     fn test_iterator() {
-        let expect = vec!["test", "te2", "test3"];
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
 
         tmp.into_iter().for_each(|x| println!("{:?}", x));
         //assert_ne!(tmp.data, expect);
     }
     #[test]
     fn test_iterator_non_consuming() {
-        let expect = vec!["test", "te2", "test3"];
-        let tmp = &CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let expect = ["test", "te2", "test3"];
+        let tmp = &CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
 
         tmp.into_iter()
-            .for_each(|x| println!("{}", x.join(&tmp.delimiter.to_string())));
+            .for_each(|x| println!("{}", x.join(&tmp.delimiter().to_string())));
         assert_ne!(tmp.data, expect);
     }
 
     #[test]
     fn test_write_to_file() {
-        let tmp = &CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = &CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
 
-        fs::remove_file("testdata/test.csv");
+        let _ = fs::remove_file("testdata/test.csv");
 
-        match tmp.to_file(String::from("testdata/test.csv")) {
-            Ok(..) => assert!(true),
-            Err(E) => {
-                println!("{}", E);
-                assert!(false)
-            }
+        if let Err(e) = tmp.to_file(String::from("testdata/test.csv")) {
+            panic!("{}", e);
         }
     }
 
     #[test]
     fn test_from_file() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
+        fs::write("testdata/testfromfile.csv", "test,test2\ntest3,\n").unwrap();
 
-        match CsvData::from_file("testdata/test.csv", ',') {
+        match CsvData::from_file("testdata/testfromfile.csv", ',') {
             Ok(data) => assert_eq!(tmp, data),
-            Err(E) => assert!(false),
+            Err(e) => panic!("{}", e),
         }
     }
 
     #[test]
     fn test_from_input_output() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
         let tmp2 = tmp.clone();
-        fs::remove_file("testdata/testinputoutput.csv");
-        tmp2.to_file(String::from("testdata/testinputoutput.csv"));
+        let _ = fs::remove_file("testdata/testinputoutput.csv");
+        tmp2.to_file(String::from("testdata/testinputoutput.csv"))
+            .unwrap();
         let result = CsvData::from_file("testdata/testinputoutput.csv", ',').unwrap();
         assert_eq!(tmp, result)
     }
@@ -598,77 +989,294 @@ mod tests {
         let expected_str = expected_str.into_iter().map(|s| s.to_string()).collect();
         let expect = CsvData {
             data: expected_str,
-            delimiter: ',',
+            dialect: Dialect::default(),
             line_width: 3,
+            headers: None,
         };
         let tmp2 = expect.clone();
-        fs::remove_file("testdata/testinputoutputfromtext.csv");
-        tmp2.to_file(String::from("testdata/testinputoutputfromtext.csv"));
+        let _ = fs::remove_file("testdata/testinputoutputfromtext.csv");
+        tmp2.to_file(String::from("testdata/testinputoutputfromtext.csv"))
+            .unwrap();
         let result = CsvData::from_file("testdata/testinputoutputfromtext.csv", ',').unwrap();
         assert_eq!(expect, result)
     }
 
+    #[test]
+    fn test_round_trip_quoting() {
+        let cells = vec!["a,b", "say \"hi\"", "x\ny", "", "1.5", " ", "plain", ""];
+        let tmp = CsvData::new(cells.into_iter().map(|s| s.to_string()).collect(), ',', 2).unwrap();
+
+        for (i, style) in [
+            QuoteStyle::Minimal,
+            QuoteStyle::Always,
+            QuoteStyle::NonNumeric,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let file_name = format!("testdata/testroundtrip{}.csv", i);
+            tmp.to_file_with_quoting(file_name.clone(), style).unwrap();
+            let result = CsvData::from_file(&file_name, ',').unwrap();
+            assert_eq!(tmp, result);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_dialect() {
+        let text = "id|note\n1|'a|b'\n2|'it''s'\n";
+        let dialect = Dialect {
+            quote: '\'',
+            terminator: LineTerminator::CrLf,
+            has_headers: true,
+            ..Dialect::new('|')
+        };
+        let tmp = ReaderBuilder::new()
+            .dialect(dialect)
+            .read_text(text)
+            .unwrap();
+        assert_eq!(tmp.dialect(), dialect);
+
+        let file_name = "testdata/testdialect.csv";
+        tmp.to_file(file_name.to_string()).unwrap();
+        assert_eq!(
+            fs::read_to_string(file_name).unwrap(),
+            text.replace('\n', "\r\n")
+        );
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        let windows = CsvData::from_text("a,b\r\nc,d".to_string(), ',').unwrap();
+        let unix = CsvData::from_text("c,d\ne,f".to_string(), ',').unwrap();
+        assert_eq!(windows.data, ["a", "b", "c", "d"]);
+        assert_eq!(windows.intersection(&unix).unwrap().data, ["c", "d"]);
+
+        let text = "id,note\r\n1,\"a\r\nb\"\r\n";
+        let tmp = ReaderBuilder::new()
+            .has_headers(true)
+            .preserve_terminator(true)
+            .read_text(text)
+            .unwrap();
+        assert_eq!(tmp.data, ["1", "a\r\nb"]);
+        let file_name = "testdata/testcrlf.csv";
+        tmp.to_file(file_name.to_string()).unwrap();
+        assert_eq!(fs::read_to_string(file_name).unwrap(), text);
+    }
+
+    #[test]
+    fn test_from_file_legacy_encoding() {
+        let file_name = "testdata/testlatin1.csv";
+        fs::write(file_name, b"name,price\nJos\xe9,\x805\n").unwrap();
+        let tmp = CsvData::from_file_with_headers(file_name, ',', true).unwrap();
+        assert_eq!(tmp.data, ["Jos\u{e9}", "\u{20ac}5"]);
+    }
+
+    #[test]
+    fn test_set_operation_dialect_mismatch() {
+        let tmp = CsvData::from_text("a;b".to_string(), ';').unwrap();
+        let quoted = ReaderBuilder::new()
+            .delimiter(';')
+            .quote('\'')
+            .read_text("a;b")
+            .unwrap();
+
+        assert!(matches!(
+            tmp.union(&quoted),
+            Err(CsvError::DialectMismatch { .. })
+        ));
+        let converted = quoted.with_dialect(tmp.dialect());
+        assert_eq!(tmp.intersection(&converted).unwrap().data, ["a", "b"]);
+
+        let trimmed = ReaderBuilder::new()
+            .delimiter(';')
+            .trim(Trim::All)
+            .comment(Some('#'))
+            .read_text("# note\n a ; b")
+            .unwrap();
+        assert_eq!(tmp.intersection(&trimmed).unwrap().data, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_round_trip_single_empty_column() {
+        let tmp = CsvData::new(
+            vec!["a".to_string(), "".to_string(), "b".to_string()],
+            ',',
+            1,
+        )
+        .unwrap();
+        tmp.to_file(String::from("testdata/testroundtripempty.csv"))
+            .unwrap();
+        let result = CsvData::from_file("testdata/testroundtripempty.csv", ',').unwrap();
+        assert_eq!(tmp, result);
+    }
+
+    #[test]
+    fn test_to_writer() {
+        let tmp = CsvData::from_text("a,b\nc,\"d,e\"".to_string(), ',').unwrap();
+        let mut buf = Vec::new();
+        tmp.to_writer(CsvWriter::new(&mut buf, ';')).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "a;b\nc;d,e\n");
+    }
+
+    #[test]
+    fn test_from_text_with_headers() {
+        let tmp =
+            CsvData::from_text_with_headers("id,name\n1,a\n2,b,extra".to_string(), ',').unwrap();
+        assert_eq!(
+            tmp.headers(),
+            Some(&["id".to_string(), "name".to_string(), "".to_string()][..])
+        );
+        assert_eq!(tmp.data, vec!["1", "a", "", "2", "b", "extra"]);
+        assert_eq!(tmp.column_index("name"), Some(1));
+        assert_eq!(tmp.column_index("missing"), None);
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let tmp = CsvData::from_reader("id,name\n\n1,a\n".as_bytes(), ',', true).unwrap();
+        assert_eq!(tmp.column_index("name"), Some(1));
+        assert_eq!(tmp.data, vec!["1", "a"]);
+    }
+
+    #[test]
+    fn test_from_file_with_headers() {
+        fs::write("testdata/testheaders.csv", "id,name\n1,a\n").unwrap();
+        let tmp = CsvData::from_file_with_headers("testdata/testheaders.csv", ',', true).unwrap();
+        assert_eq!(tmp.column_index("id"), Some(0));
+        assert_eq!(tmp.data, vec!["1", "a"]);
+
+        let plain = CsvData::from_file("testdata/testheaders.csv", ',').unwrap();
+        assert_eq!(plain.headers(), None);
+        assert_eq!(plain.data, vec!["id", "name", "1", "a"]);
+    }
+
+    #[test]
+    fn test_headers_written_once() {
+        let tmp = CsvData::from_text_with_headers("id,name\n2,b\n1,a".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text_with_headers("id,name\n1,a\n3,c".to_string(), ',').unwrap();
+        let result = tmp.union(&tmp2).unwrap();
+        assert_eq!(result.data, vec!["1", "a", "1", "a", "2", "b", "3", "c"]);
+
+        let mut buf = Vec::new();
+        result.to_writer(CsvWriter::new(&mut buf, ',')).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "id,name\n1,a\n1,a\n2,b\n3,c\n"
+        );
+    }
+
+    #[test]
+    fn test_set_operations_ignore_headers() {
+        let tmp = CsvData::from_text_with_headers("id\n1\n2".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text_with_headers("id\n2\n3".to_string(), ',').unwrap();
+        let intersection = tmp.intersection(&tmp2).unwrap();
+        assert_eq!(intersection.data, vec!["2"]);
+        assert_eq!(intersection.headers(), Some(&["id".to_string()][..]));
+        assert_eq!(tmp.difference(&tmp2).unwrap().data, vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_set_operations_header_mismatch() {
+        let tmp = CsvData::from_text_with_headers("id\n1".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text_with_headers("key\n1".to_string(), ',').unwrap();
+        let plain = CsvData::from_text("1".to_string(), ',').unwrap();
+        assert!(matches!(
+            tmp.union(&tmp2),
+            Err(CsvError::HeaderMismatch { .. })
+        ));
+        assert!(matches!(
+            union_all(&[tmp.clone(), plain.clone(), tmp2], ',', 0),
+            Err(CsvError::HeaderMismatch { .. })
+        ));
+        assert_eq!(tmp.union(&plain).unwrap().headers().unwrap(), ["id"]);
+    }
+
+    #[test]
+    fn test_with_headers() {
+        let tmp = CsvData::from_text("1,a".to_string(), ',').unwrap();
+        let result = tmp.with_headers(vec!["id".to_string(), "name".to_string(), "x".to_string()]);
+        assert_eq!(result.column_index("x"), Some(2));
+        assert_eq!(result.data, vec!["1", "a", ""]);
+    }
+
+    #[test]
+    fn test_transpose_with_headers() {
+        let tmp = CsvData::from_text_with_headers("a,b\n1,2".to_string(), ',').unwrap();
+        let result = tmp.transpose();
+        assert_eq!(result.headers(), None);
+        assert_eq!(result.data, vec!["a", "1", "b", "2"]);
+    }
+
     #[test]
     fn test_union() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2)
+                .unwrap();
         let expected = CsvData::from_raw_string(
             "test,test2,test,test2,test3,,test3,test4,test5,test6".to_string(),
             ',',
             2,
-        );
+        )
+        .unwrap();
         let result = tmp.union(&tmp2).unwrap();
         assert_eq!(expected, result)
     }
 
     #[test]
     fn test_union_2() {
-        let tmp = CsvData::from_raw_string("test,test2, , ,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2, , ,test3".to_string(), ',', 2).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2)
+                .unwrap();
         let expected = CsvData::from_raw_string(
             " , ,test,test2,test,test2,test3,,test3,test4,test5,test6".to_string(),
             ',',
             2,
-        );
+        )
+        .unwrap();
         let result = tmp.union(&tmp2).unwrap();
         assert_eq!(expected, result)
     }
 
     #[test]
     fn test_union_different_widths() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 3);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 3).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 4);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 4)
+                .unwrap();
         let expected = CsvData::from_raw_string(
             "test,test2,test3,,test,test2,test3,test4,test5,test6,,".to_string(),
             ',',
             4,
-        );
+        )
+        .unwrap();
         let result = tmp.union(&tmp2).unwrap();
         assert_eq!(expected, result)
     }
 
     #[test]
     fn test_union_different_widths_rev() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 1);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 1)
+                .unwrap();
         let expected = CsvData::from_raw_string(
             "test, ,test,test2,test2, ,test3,,test3, ,test4, ,test5, ,test6, ".to_string(),
             ',',
             2,
-        );
+        )
+        .unwrap();
         let result = tmp.union(&tmp2).unwrap();
         assert_eq!(expected, result)
     }
     #[test]
     fn test_intersection() {
-        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2);
+        let tmp = CsvData::from_raw_string("test,test2,test3".to_string(), ',', 2).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2);
-        let expected = CsvData::from_raw_string("test,test2".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 2)
+                .unwrap();
+        let expected = CsvData::from_raw_string("test,test2".to_string(), ',', 2).unwrap();
         let result = tmp.intersection(&tmp2).unwrap();
         println!("{:?} {:?}", tmp, result);
         assert_eq!(expected, result)
@@ -676,10 +1284,12 @@ mod tests {
 
     #[test]
     fn test_intersection_larger() {
-        let tmp = CsvData::from_raw_string("test,test2,test3,test4".to_string(), ',', 4);
+        let tmp = CsvData::from_raw_string("test,test2,test3,test4".to_string(), ',', 4).unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 4);
-        let expected = CsvData::from_raw_string("test,test2,test3,test4".to_string(), ',', 4);
+            CsvData::from_raw_string("test,test2,test3,test4,test5,test6".to_string(), ',', 4)
+                .unwrap();
+        let expected =
+            CsvData::from_raw_string("test,test2,test3,test4".to_string(), ',', 4).unwrap();
         let result = tmp.intersection(&tmp2).unwrap();
         println!("{:?} {:?}", tmp, result);
         assert_eq!(expected, result)
@@ -688,14 +1298,17 @@ mod tests {
     #[test]
     fn test_intersection_diff() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 = CsvData::from_raw_string(
             "test,test2,test3,test4,test5,test6,test3,test4".to_string(),
             ',',
             2,
-        );
+        )
+        .unwrap();
         let expected =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let result = tmp.intersection(&tmp2).unwrap();
         println!("{:?} {:?}", tmp, result);
         assert_eq!(expected, result)
@@ -704,13 +1317,15 @@ mod tests {
     #[test]
     fn test_difference() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 = CsvData::from_raw_string(
             "test,test2,test3,test4,test5,test6,test3,test4".to_string(),
             ',',
             2,
-        );
-        let expected = CsvData::from_raw_string("test5,test6".to_string(), ',', 2);
+        )
+        .unwrap();
+        let expected = CsvData::from_raw_string("test5,test6".to_string(), ',', 2).unwrap();
         let result = tmp.difference(&tmp2).unwrap();
         println!("{:?} {:?}", tmp, result);
         assert_eq!(expected, result)
@@ -719,28 +1334,202 @@ mod tests {
     #[test]
     fn test_difference_empty() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
-        let expected = CsvData::from_raw_string(String::new(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
+        let expected = CsvData::from_raw_string(String::new(), ',', 2).unwrap();
         let result = tmp.difference(&tmp2).unwrap();
         println!("{:?} {:?}", tmp, result);
         assert_eq!(expected, result)
     }
 
+    #[test]
+    fn test_set_operations_first_seen() {
+        let options = SetOptions::default().order(RowOrder::FirstSeen);
+        let tmp = CsvData::from_text("c\na\nb\na".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("d\nb\na".to_string(), ',').unwrap();
+
+        assert_eq!(
+            tmp.union_with(&tmp2, &options).unwrap().data,
+            vec!["c", "a", "a", "a", "b", "b", "d"]
+        );
+        assert_eq!(
+            tmp.intersection_with(&tmp2, &options).unwrap().data,
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            tmp.difference_with(&tmp2, &options).unwrap().data,
+            vec!["c", "a", "d"]
+        );
+        assert_eq!(
+            tmp.union(&tmp2).unwrap().data,
+            vec!["a", "a", "a", "b", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_all_first_seen() {
+        let options = SetOptions::default().order(RowOrder::FirstSeen);
+        let tmp = CsvData::from_text("z\ny\nx".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("x\nz\nw".to_string(), ',').unwrap();
+        let tmp3 = CsvData::from_text("v\nz\nx".to_string(), ',').unwrap();
+        let vec = vec![tmp, tmp2, tmp3];
+
+        assert_eq!(
+            intersection_all_with(&vec, &options).unwrap().data,
+            vec!["z", "x"]
+        );
+        assert_eq!(
+            difference_all_with(&vec, &options).unwrap().data,
+            vec!["y", "w", "v"]
+        );
+        assert_eq!(difference_all(&vec).unwrap().data, vec!["v", "w", "y"]);
+    }
+
+    #[test]
+    fn test_set_operations_distinct() {
+        let distinct = SetOptions::default().semantics(Semantics::Distinct);
+        let tmp = CsvData::from_text("a\na\na\nb\nc\nc".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("a\na\nb\nb\nd".to_string(), ',').unwrap();
+
+        // UNION ALL versus UNION
+        assert_eq!(
+            tmp.union(&tmp2).unwrap().data,
+            vec!["a", "a", "a", "a", "a", "b", "b", "b", "c", "c", "d"]
+        );
+        assert_eq!(
+            tmp.union_with(&tmp2, &distinct).unwrap().data,
+            vec!["a", "b", "c", "d"]
+        );
+
+        // INTERSECT ALL versus INTERSECT
+        assert_eq!(tmp.intersection(&tmp2).unwrap().data, vec!["a", "a", "b"]);
+        assert_eq!(
+            tmp.intersection_with(&tmp2, &distinct).unwrap().data,
+            vec!["a", "b"]
+        );
+
+        assert_eq!(
+            tmp.difference(&tmp2).unwrap().data,
+            vec!["a", "c", "c", "b", "d"]
+        );
+        assert_eq!(
+            tmp.difference_with(&tmp2, &distinct).unwrap().data,
+            vec!["c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_except() {
+        let distinct = SetOptions::default().semantics(Semantics::Distinct);
+        let tmp = CsvData::from_text(
+            "a
+a
+a
+b
+c
+c"
+            .to_string(),
+            ',',
+        )
+        .unwrap();
+        let tmp2 = CsvData::from_text(
+            "a
+b
+b
+d"
+            .to_string(),
+            ',',
+        )
+        .unwrap();
+
+        // EXCEPT ALL versus EXCEPT
+        assert_eq!(tmp.except(&tmp2).unwrap().data, vec!["a", "a", "c", "c"]);
+        assert_eq!(tmp.minus(&tmp2).unwrap(), tmp.except(&tmp2).unwrap());
+        assert_eq!(tmp.except_with(&tmp2, &distinct).unwrap().data, vec!["c"]);
+        assert_eq!(tmp2.except(&tmp).unwrap().data, vec!["b", "d"]);
+
+        assert_eq!(
+            tmp.symmetric_difference(&tmp2).unwrap().data,
+            vec!["a", "a", "c", "c", "b", "d"]
+        );
+        assert_eq!(
+            tmp.symmetric_difference_with(&tmp2, &distinct)
+                .unwrap()
+                .data,
+            vec!["c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_all_distinct() {
+        let distinct = SetOptions::default().semantics(Semantics::Distinct);
+        let tmp = CsvData::from_text("b\na\na\nc".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("a\na\nd\nd".to_string(), ',').unwrap();
+        let vec = vec![tmp, tmp2];
+
+        assert_eq!(
+            union_all(&vec, ',', 1).unwrap().data,
+            vec!["b", "a", "a", "c", "a", "a", "d", "d"]
+        );
+        assert_eq!(
+            union_all_with(&vec, ',', 1, &distinct).unwrap().data,
+            vec!["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            union_all_with(&vec, ',', 1, &SetOptions::default())
+                .unwrap()
+                .data,
+            vec!["a", "a", "a", "a", "b", "c", "d", "d"]
+        );
+        assert_eq!(
+            intersection_all_with(&vec, &distinct).unwrap().data,
+            vec!["a"]
+        );
+        assert_eq!(difference_all(&vec).unwrap().data, vec!["b", "c", "d", "d"]);
+        assert_eq!(
+            difference_all_with(&vec, &distinct).unwrap().data,
+            vec!["b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_all_single_input() {
+        let vec = vec![CsvData::from_text("b,2\na,1\nb,2".to_string(), ',').unwrap()];
+        let distinct = SetOptions::default().semantics(Semantics::Distinct);
+
+        let sorted = ["a", "1", "b", "2", "b", "2"];
+        let options = SetOptions::default();
+        assert_eq!(union_all_with(&vec, ',', 0, &options).unwrap().data, sorted);
+        assert_eq!(intersection_all(&vec).unwrap().data, sorted);
+        assert_eq!(
+            union_all_with(&vec, ',', 0, &distinct).unwrap().data,
+            ["a", "1", "b", "2"]
+        );
+        assert_eq!(
+            intersection_all_with(&vec, &distinct).unwrap().data,
+            ["a", "1", "b", "2"]
+        );
+    }
+
     #[test]
     fn test_union_all() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp3 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
 
-        let mut vec = vec![tmp, tmp2, tmp3];
+        let vec = vec![tmp, tmp2, tmp3];
 
-        let expected = CsvData::from_raw_string("test,test2,test3,test4,test3,test4,test,test2,test3,test4,test3,test4,test,test2,test3,test4,test3,test4".to_string(), ',', 2);
-        let result = union_all(&vec, ',', 2);
+        let expected = CsvData::from_raw_string("test,test2,test3,test4,test3,test4,test,test2,test3,test4,test3,test4,test,test2,test3,test4,test3,test4".to_string(), ',', 2).unwrap();
+        let result = union_all(&vec, ',', 2).unwrap();
 
         assert_eq!(expected, result)
     }
@@ -748,16 +1537,19 @@ mod tests {
     #[test]
     fn test_union_all_change_width() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp3 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
 
         let vec = vec![tmp, tmp2, tmp3];
 
-        let expected = CsvData::from_raw_string("test,test2,,,test3,test4,,,test3,test4,,,test,test2,,,test3,test4,,,test3,test4,,,test,test2,,,test3,test4,,,test3,test4,,".to_string(), ',', 4);
-        let result = union_all(&vec, ',', 4);
+        let expected = CsvData::from_raw_string("test,test2,,,test3,test4,,,test3,test4,,,test,test2,,,test3,test4,,,test3,test4,,,test,test2,,,test3,test4,,,test3,test4,,".to_string(), ',', 4).unwrap();
+        let result = union_all(&vec, ',', 4).unwrap();
 
         assert_eq!(expected, result)
     }
@@ -765,16 +1557,20 @@ mod tests {
     #[test]
     fn test_intersection_all() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp3 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
 
-        let mut vec = vec![tmp, tmp2, tmp3];
+        let vec = vec![tmp, tmp2, tmp3];
 
         let expected =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let result = intersection_all(&vec).unwrap();
 
         assert_eq!(expected, result)
@@ -783,15 +1579,18 @@ mod tests {
     #[test]
     fn test_intersection_all_different_widths() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,,,test3,test4,test3,test4".to_string(), ',', 4);
+            CsvData::from_raw_string("test,test2,,,test3,test4,test3,test4".to_string(), ',', 4)
+                .unwrap();
         let tmp3 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
 
-        let mut vec = vec![tmp, tmp2, tmp3];
+        let vec = vec![tmp, tmp2, tmp3];
 
-        let expected = CsvData::from_raw_string("test,test2,,".to_string(), ',', 4);
+        let expected = CsvData::from_raw_string("test,test2,,".to_string(), ',', 4).unwrap();
         let result = intersection_all(&vec).unwrap();
 
         assert_eq!(expected, result)
@@ -800,16 +1599,40 @@ mod tests {
     #[test]
     fn test_intersection_all_none() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
-        let tmp3 = CsvData::from_raw_string("asdas,addsad".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
+        let tmp3 = CsvData::from_raw_string("asdas,addsad".to_string(), ',', 2).unwrap();
 
         let vec = vec![tmp, tmp2, tmp3];
 
-        let result = intersection_all(&vec);
+        let result = intersection_all(&vec).unwrap();
         println!("{:?}", result);
-        assert!(result.is_none())
+        assert!(result.data.is_empty())
+    }
+
+    #[test]
+    fn test_intersection_all_delimiter_mismatch() {
+        let tmp = CsvData::from_raw_string("test,test2".to_string(), ',', 2).unwrap();
+        let tmp2 = CsvData::from_raw_string("test;test2".to_string(), ';', 2).unwrap();
+
+        let result = intersection_all(&[tmp, tmp2]);
+        assert!(matches!(
+            result,
+            Err(CsvError::DelimiterMismatch {
+                left: ',',
+                right: ';'
+            })
+        ));
+    }
+
+    #[test]
+    fn test_all_empty_input() {
+        assert!(matches!(union_all(&[], ',', 1), Err(CsvError::EmptyInput)));
+        assert!(matches!(intersection_all(&[]), Err(CsvError::EmptyInput)));
+        assert!(matches!(difference_all(&[]), Err(CsvError::EmptyInput)));
     }
 
     #[test]
@@ -818,13 +1641,14 @@ mod tests {
             "test,test2,test3,test4,test3,test4,adfas,addsad".to_string(),
             ',',
             2,
-        );
-        let tmp2 = CsvData::from_raw_string("test3,test4,test3,test4".to_string(), ',', 2);
-        let tmp3 = CsvData::from_raw_string("adfas,addsad".to_string(), ',', 2);
+        )
+        .unwrap();
+        let tmp2 = CsvData::from_raw_string("test3,test4,test3,test4".to_string(), ',', 2).unwrap();
+        let tmp3 = CsvData::from_raw_string("adfas,addsad".to_string(), ',', 2).unwrap();
 
         let vec = vec![tmp, tmp2, tmp3];
-        let expected = CsvData::from_raw_string("test,test2".to_string(), ',', 2);
-        let result = difference_all(&vec);
+        let expected = CsvData::from_raw_string("test,test2".to_string(), ',', 2).unwrap();
+        let result = difference_all(&vec).unwrap();
 
         assert_eq!(result, expected);
     }
@@ -835,17 +1659,41 @@ mod tests {
             "test,test2,test3,test4,test3,test4,adfas,addsad".to_string(),
             ',',
             2,
-        );
-        let tmp2 = CsvData::from_raw_string("test3,test4,test3,test4".to_string(), ',', 2);
-        let tmp3 = CsvData::from_raw_string("test,test2,adfas,addsad".to_string(), ',', 2);
+        )
+        .unwrap();
+        let tmp2 = CsvData::from_raw_string("test3,test4,test3,test4".to_string(), ',', 2).unwrap();
+        let tmp3 = CsvData::from_raw_string("test,test2,adfas,addsad".to_string(), ',', 2).unwrap();
 
         let vec = vec![tmp, tmp2, tmp3];
-        let expected = CsvData::from_raw_string("".to_string(), ',', 2);
-        let result = difference_all(&vec);
+        let expected = CsvData::from_raw_string("".to_string(), ',', 2).unwrap();
+        let result = difference_all(&vec).unwrap();
 
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_new_ragged() {
+        let cells = |n: usize| (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(CsvData::new(cells(6), ',', 3).unwrap().rows().count(), 2);
+        assert!(matches!(
+            CsvData::new(cells(5), ',', 3),
+            Err(CsvError::RaggedRow {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(CsvData::new(cells(1), ',', 0).is_err());
+        let empty = CsvData::new(Vec::new(), ',', 0).unwrap();
+        assert_eq!(empty.transpose(), empty);
+        assert_eq!(
+            CsvData::from_raw_string("a,b,c,d".to_string(), ',', 3)
+                .unwrap()
+                .data,
+            ["a", "b", "c", "d", "", ""]
+        );
+    }
+
     #[test]
     fn test_transpose_square() {
         let tmp = CsvData::from_raw_string(
@@ -855,12 +1703,14 @@ mod tests {
                 .to_string(),
             ',',
             3,
-        );
+        )
+        .unwrap();
         let expected = CsvData::from_raw_string(
             "test,test3,test5,test2,test4,test6,adsaf,asdas,test7".to_string(),
             ',',
             3,
-        );
+        )
+        .unwrap();
 
         assert_eq!(tmp.transpose(), expected);
     }
@@ -876,12 +1726,14 @@ mod tests {
                 .to_string(),
             ',',
             2,
-        );
+        )
+        .unwrap();
         let expected = CsvData::from_raw_string(
             "test,adsaf,test4,test5,test7,test2,test3,asdas,test6,".to_string(),
             ',',
             5,
-        );
+        )
+        .unwrap();
 
         assert_eq!(tmp.transpose(), expected);
     }
@@ -889,11 +1741,13 @@ mod tests {
     #[test]
     fn test_pad() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
 
-        let result = pad(&[tmp, tmp2], 4);
+        let result = pad(&[tmp, tmp2], 4).unwrap();
 
         assert!(result
             .iter()
@@ -902,13 +1756,66 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_pad_smaller() {
         let tmp =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
         let tmp2 =
-            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2);
+            CsvData::from_raw_string("test,test2,test3,test4,test3,test4".to_string(), ',', 2)
+                .unwrap();
+
+        let result = pad(&[tmp, tmp2], 1);
+        assert!(matches!(
+            result,
+            Err(CsvError::RaggedRow {
+                line: 1,
+                expected: 1,
+                found: 2
+            })
+        ));
+    }
 
-        let _result = pad(&[tmp, tmp2], 1);
+    #[test]
+    fn test_pad_smaller_headers() {
+        let tmp = CsvData::from_text_with_headers("a\n1,2".to_string(), ',').unwrap();
+
+        let result = pad(&[tmp], 1);
+        assert!(matches!(
+            result,
+            Err(CsvError::RaggedRow {
+                line: 1,
+                expected: 1,
+                found: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_set_operation_delimiter_mismatch() {
+        let tmp = CsvData::from_raw_string("test,test2".to_string(), ',', 2).unwrap();
+        let tmp2 = CsvData::from_raw_string("test;test2".to_string(), ';', 2).unwrap();
+
+        assert!(tmp.union(&tmp2).is_err());
+        assert!(tmp.intersection(&tmp2).is_err());
+        assert!(tmp.difference(&tmp2).is_err());
+        assert!(tmp.union(&tmp2.with_delimiter(',')).is_ok());
+    }
+
+    #[test]
+    fn test_from_file_errors() {
+        fs::write("testdata/testempty.csv", "\n\n").unwrap();
+        assert!(matches!(
+            CsvData::from_file("testdata/testempty.csv", ','),
+            Err(CsvError::EmptyInput)
+        ));
+        assert!(matches!(
+            CsvData::from_file("testdata/does_not_exist.csv", ','),
+            Err(CsvError::Io(_))
+        ));
+        assert!(matches!(
+            CsvData::from_text("a,\"b".to_string(), ','),
+            Err(CsvError::Parse { line: 1, column: 3 })
+        ));
+        assert!(CsvData::from_raw_string("a".to_string(), ',', 0).is_err());
     }
 }
@@ -0,0 +1,382 @@
+//! How a CSV file is written, and guessing that from a sample of it.
+//!
+//! [`sniff`] looks at the first lines of a sample. The delimiter is the
+//! candidate that splits those lines into the same number of fields most
+//! consistently; the header row is detected by comparing the first row with
+//! the ones below it, column by column. The quote is `'` only if more
+//! fields start with it than with `"` and the sample parses cleanly with it.
+
+use std::io::BufRead;
+
+use crate::encoding::{detect_encoding, Decoder, Encoding};
+use crate::error::CsvError;
+use crate::sort::parse_number;
+use crate::tokenizer::RecordParser;
+
+/// Delimiters [`sniff`] chooses from, preferred in this order on a tie.
+const CANDIDATE_DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+/// Number of lines of the sample [`sniff`] looks at.
+const SAMPLE_LINES: usize = 100;
+
+/// The character sequence that ends a line.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LineTerminator {
+    /// `\n`, as on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows.
+    CrLf,
+    /// `\r`, as on classic Mac OS.
+    Cr,
+}
+
+impl LineTerminator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineTerminator::Lf => "\n",
+            LineTerminator::CrLf => "\r\n",
+            LineTerminator::Cr => "\r",
+        }
+    }
+}
+
+/// Which cells have the whitespace around them removed on read.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Trim {
+    #[default]
+    None,
+    /// Only the cells of the header row.
+    Headers,
+    /// Only the cells of data rows.
+    Fields,
+    All,
+}
+
+impl Trim {
+    pub(crate) fn headers(&self) -> bool {
+        matches!(self, Trim::Headers | Trim::All)
+    }
+
+    pub(crate) fn fields(&self) -> bool {
+        matches!(self, Trim::Fields | Trim::All)
+    }
+}
+
+/// The format of a CSV file. Set it up with a
+/// [`ReaderBuilder`](crate::ReaderBuilder) or a
+/// [`WriterBuilder`](crate::WriterBuilder); a [`CsvData`](crate::CsvData)
+/// remembers the dialect it was read with and writes itself in it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    /// Character that makes the next one inside a quoted field literal, as
+    /// in `"say \"hi\""`. A quote can always be escaped by doubling it.
+    pub escape: Option<char>,
+    /// Line terminator written after every record. On read, `\n`, `\r\n`
+    /// and `\r` all end a record, whatever this is set to.
+    pub terminator: LineTerminator,
+    /// Lines starting with this character are skipped on read.
+    pub comment: Option<char>,
+    /// Whether the first line is a header row.
+    pub has_headers: bool,
+    pub trim: Trim,
+}
+
+impl Dialect {
+    /// The default dialect with another delimiter.
+    pub fn new(delimiter: char) -> Self {
+        Dialect {
+            delimiter,
+            ..Dialect::default()
+        }
+    }
+
+    /// Whether tables in the two dialects can be combined as they are: they
+    /// are written the same way. The header flag, trimming and comments only
+    /// matter when reading, so they may differ.
+    pub(crate) fn compatible(&self, other: &Dialect) -> bool {
+        self.delimiter == other.delimiter
+            && self.quote == other.quote
+            && self.escape == other.escape
+            && self.terminator == other.terminator
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: ',',
+            quote: '"',
+            escape: None,
+            terminator: LineTerminator::default(),
+            comment: None,
+            has_headers: false,
+            trim: Trim::default(),
+        }
+    }
+}
+
+/// Guesses the dialect of the text `sample` starts with. Whatever cannot be
+/// told from the sample is left at its [default](Dialect::default), so a
+/// sample without any candidate delimiter yields a comma.
+pub fn sniff(sample: &str) -> Dialect {
+    let dialect = sniff_with_quote(sample, sniff_quote(sample));
+    // A quote the sample does not parse with is more likely a text prefix,
+    // as Excel writes `'00123`, than a quote.
+    match dialect.quote == '"' || parses_cleanly(sample, &dialect) {
+        true => dialect,
+        false => sniff_with_quote(sample, '"'),
+    }
+}
+
+fn sniff_with_quote(sample: &str, quote: char) -> Dialect {
+    let (lines, terminator) = split_lines(sample, quote);
+
+    let mut best: Option<(usize, char)> = None;
+    for delimiter in CANDIDATE_DELIMITERS {
+        let mut widths: Vec<usize> = lines
+            .iter()
+            .map(|line| count_outside_quotes(line, delimiter, quote) + 1)
+            .collect();
+        widths.sort_unstable();
+        // Number of lines that share the most common width, if it is wider
+        // than a single field.
+        let agreeing = widths
+            .chunk_by(|a, b| a == b)
+            .filter(|run| run[0] > 1)
+            .map(|run| run.len())
+            .max()
+            .unwrap_or(0);
+        if agreeing > 0 && best.is_none_or(|(most, _)| agreeing > most) {
+            best = Some((agreeing, delimiter));
+        }
+    }
+    let delimiter = best.map_or(',', |(_, delimiter)| delimiter);
+
+    let rows: Vec<Vec<String>> = lines
+        .iter()
+        .filter_map(|line| parse_line(line, delimiter, quote))
+        .collect();
+    Dialect {
+        delimiter,
+        quote,
+        has_headers: has_headers(&rows),
+        terminator: terminator.unwrap_or_default(),
+        ..Dialect::default()
+    }
+}
+
+/// Like [`sniff`], on the data `reader` has buffered, without consuming it.
+/// Reading from `reader` afterwards starts at the same place as before. The
+/// data is decoded in the encoding it is detected to be in.
+pub fn sniff_reader<R: BufRead>(reader: &mut R) -> Result<Dialect, CsvError> {
+    let encoding = detect_encoding(reader.fill_buf()?);
+    sniff_reader_with_encoding(reader, encoding)
+}
+
+/// Like [`sniff_reader`], decoding the data in `encoding`.
+pub fn sniff_reader_with_encoding<R: BufRead>(
+    reader: &mut R,
+    encoding: Encoding,
+) -> Result<Dialect, CsvError> {
+    let bytes = reader.fill_buf()?;
+    let mut sample = String::new();
+    // Whatever decodes before an error is enough to sniff.
+    let _ = Decoder::new(encoding).decode(bytes, &mut sample);
+    Ok(sniff(&sample))
+}
+
+/// `'` if more fields start with it than with `"` and some field also ends
+/// with it, else `"`.
+fn sniff_quote(sample: &str) -> char {
+    let is_boundary = |c: &char| *c == '\n' || *c == '\r' || CANDIDATE_DELIMITERS.contains(c);
+    let (mut double, mut single) = (0, 0);
+    let mut closed = false;
+    let mut field_start = true;
+    let mut chars = sample.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field_start => double += 1,
+            '\'' if field_start => single += 1,
+            '\'' => closed |= chars.peek().is_none_or(is_boundary),
+            _ => {}
+        }
+        field_start = is_boundary(&c);
+    }
+    if single > double && closed {
+        '\''
+    } else {
+        '"'
+    }
+}
+
+/// The first non-blank lines of `sample`, split at line breaks outside
+/// quotes, and the first line terminator seen.
+fn split_lines(sample: &str, quote: char) -> (Vec<&str>, Option<LineTerminator>) {
+    let mut lines = Vec::new();
+    let mut terminator = None;
+    let mut quoted = false;
+    let mut start = 0;
+    let mut chars = sample.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            quoted = !quoted;
+            continue;
+        }
+        if quoted || (c != '\n' && c != '\r') {
+            continue;
+        }
+        let found = match c {
+            '\r' if chars.next_if(|&(_, next)| next == '\n').is_some() => LineTerminator::CrLf,
+            '\r' => LineTerminator::Cr,
+            _ => LineTerminator::Lf,
+        };
+        terminator.get_or_insert(found);
+        if i > start {
+            lines.push(&sample[start..i]);
+        }
+        start = i + found.as_str().len();
+        if lines.len() == SAMPLE_LINES {
+            return (lines, terminator);
+        }
+    }
+    if start < sample.len() {
+        lines.push(&sample[start..]);
+    }
+    (lines, terminator)
+}
+
+fn count_outside_quotes(line: &str, delimiter: char, quote: char) -> usize {
+    let mut quoted = false;
+    let mut count = 0;
+    for c in line.chars() {
+        if c == quote {
+            quoted = !quoted;
+        } else if c == delimiter && !quoted {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The fields of one line, or `None` if its quoting is malformed, as it may
+/// be when the sample ends inside a quoted field.
+fn parse_line(line: &str, delimiter: char, quote: char) -> Option<Vec<String>> {
+    let mut parser = RecordParser::new(&Dialect {
+        quote,
+        ..Dialect::new(delimiter)
+    });
+    for c in line.chars() {
+        parser.feed(c).ok()?;
+    }
+    parser.finish().ok()?
+}
+
+/// Whether `sample` parses in `dialect` as far as it goes. The last record
+/// may be cut off, so it is not finished.
+fn parses_cleanly(sample: &str, dialect: &Dialect) -> bool {
+    let mut parser = RecordParser::new(dialect);
+    sample.chars().all(|c| parser.feed(c).is_ok())
+}
+
+/// Whether the first row stands out from the rest. Every column whose other
+/// cells are all numbers, or all of the same length, votes for a header row
+/// if its first cell breaks that pattern and against one if it follows it.
+fn has_headers(rows: &[Vec<String>]) -> bool {
+    let Some((first, rest)) = rows.split_first() else {
+        return false;
+    };
+    let mut votes = 0;
+    for (i, header) in first.iter().enumerate() {
+        let header = header.trim();
+        let cells: Vec<&str> = rest
+            .iter()
+            .filter_map(|row| row.get(i))
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty())
+            .collect();
+        let Some(length) = cells.first().map(|cell| cell.chars().count()) else {
+            continue;
+        };
+        let stands_out = if cells.iter().all(|cell| parse_number(cell).is_some()) {
+            parse_number(header).is_none()
+        } else if cells.iter().all(|cell| cell.chars().count() == length) {
+            header.chars().count() != length
+        } else {
+            continue;
+        };
+        votes += if stands_out { 1 } else { -1 };
+    }
+    votes > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use crate::dialect::{
+        sniff, sniff_reader, sniff_reader_with_encoding, Dialect, LineTerminator,
+    };
+    use crate::encoding::Encoding;
+
+    #[test]
+    fn test_sniff_delimiters() {
+        for delimiter in [',', ';', '\t', '|'] {
+            let sample =
+                "name,amount,note\nbob,10,\"x,y\"\nann,20,z\n".replace(',', &delimiter.to_string());
+            let dialect = sniff(&sample);
+            assert_eq!(dialect.delimiter, delimiter);
+            assert!(dialect.has_headers);
+            assert_eq!(dialect.terminator, LineTerminator::Lf);
+        }
+        // The commas are all inside quotes.
+        assert_eq!(sniff("\"a,b\";c\n\"d,e\";f").delimiter, ';');
+    }
+
+    #[test]
+    fn test_sniff_quote_and_terminator() {
+        let dialect = sniff("'a;b'|1\r\n'c'|2\r\n");
+        assert_eq!(dialect.quote, '\'');
+        assert_eq!(dialect.delimiter, '|');
+        assert_eq!(dialect.terminator, LineTerminator::CrLf);
+        assert_eq!(sniff("a,b\rc,d\r").terminator, LineTerminator::Cr);
+
+        // Excel's text prefix is not a quote.
+        assert_eq!(sniff("id,code\n1,'00123\n2,'00456\n").quote, '"');
+        assert_eq!(sniff("'a,'b\n'c,'d\n").quote, '"');
+        // Neither is a quote that closes too early.
+        assert_eq!(sniff("'a',b\n'it's',c\n").quote, '"');
+        assert_eq!(sniff("'a',b\n'c',d").quote, '\'');
+    }
+
+    #[test]
+    fn test_sniff_without_headers() {
+        let dialect = sniff("a,1\nb,2\nc,3");
+        assert!(!dialect.has_headers);
+        assert_eq!(sniff("just one column\n"), Dialect::default());
+        assert_eq!(sniff(""), Dialect::default());
+    }
+
+    #[test]
+    fn test_sniff_reader_does_not_consume() {
+        let mut reader = BufReader::new("x;y\n1;2\n".as_bytes());
+        assert_eq!(sniff_reader(&mut reader).unwrap().delimiter, ';');
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "x;y\n");
+    }
+
+    #[test]
+    fn test_sniff_reader_with_encoding() {
+        // Too few zero bytes to be detected as UTF-16.
+        let bytes = Encoding::Utf16Be
+            .encode("\u{540d};\u{5b57}\n\u{7532};\u{4e59}\n")
+            .unwrap();
+        let mut reader = BufReader::new(&bytes[..]);
+        let dialect = sniff_reader_with_encoding(&mut reader, Encoding::Utf16Be).unwrap();
+        assert_eq!(dialect.delimiter, ';');
+        assert_eq!(reader.fill_buf().unwrap(), bytes);
+    }
+}
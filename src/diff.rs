@@ -0,0 +1,361 @@
+//! Row-level comparison of two versions of a table, matched on key columns.
+//!
+//! Unlike [`CsvData::difference`], which only returns the rows found on one
+//! side, [`CsvData::diff_by_key`] pairs the rows of both tables by key and
+//! reports which rows were added, which were removed and which cells of the
+//! remaining rows changed.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::column::Column;
+use crate::csvdata::{check_dialects, CsvData};
+use crate::dialect::Dialect;
+use crate::error::CsvError;
+use crate::keyed::KeyedRows;
+use crate::writer::{format_record, QuoteStyle};
+
+/// One cell whose value differs between the two tables.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CellChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A row present in both tables whose non-key cells differ.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ModifiedRow {
+    pub key: Vec<String>,
+    pub changes: Vec<CellChange>,
+}
+
+/// Result of [`CsvData::diff_by_key`].
+///
+/// Added and removed rows are laid out along [`columns`](DiffReport::columns):
+/// the columns of the old table followed by the ones only the new table has.
+/// `Display` renders a summary for people, [`to_csv`](DiffReport::to_csv) a
+/// table with one line per changed cell.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiffReport {
+    pub columns: Vec<String>,
+    pub key_columns: Vec<usize>,
+    pub added: Vec<Vec<String>>,
+    pub removed: Vec<Vec<String>>,
+    pub modified: Vec<ModifiedRow>,
+}
+
+/// A column of the report and where it sits in the old and the new table.
+struct DiffColumn {
+    name: String,
+    old: Option<usize>,
+    new: Option<usize>,
+}
+
+impl DiffColumn {
+    fn cell(index: Option<usize>, row: &[String]) -> String {
+        index.map(|i| row[i].clone()).unwrap_or_default()
+    }
+}
+
+impl DiffReport {
+    /// True when both tables hold the same rows.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    fn key_of(&self, row: &[String]) -> Vec<String> {
+        self.key_columns.iter().map(|&i| row[i].clone()).collect()
+    }
+
+    /// The changes as a table with the header
+    /// `change,<key columns>,column,old,new`. Added and removed rows get one
+    /// line per non-key cell, modified rows one line per changed cell.
+    pub fn to_csv(&self, delimiter: char) -> CsvData {
+        let mut headers = vec!["change".to_string()];
+        headers.extend(self.key_columns.iter().map(|&i| self.columns[i].clone()));
+        headers.extend(["column", "old", "new"].map(String::from));
+
+        let mut data = Vec::new();
+        let mut push = |change: &str, key: Vec<String>, column: &str, old: &str, new: &str| {
+            data.push(change.to_string());
+            data.extend(key);
+            data.extend([column, old, new].map(String::from));
+        };
+        let value_columns: Vec<usize> = (0..self.columns.len())
+            .filter(|i| !self.key_columns.contains(i))
+            .collect();
+        for (change, rows) in [("removed", &self.removed), ("added", &self.added)] {
+            for row in rows {
+                if value_columns.is_empty() {
+                    push(change, self.key_of(row), "", "", "");
+                }
+                for &i in &value_columns {
+                    let (old, new) = match change {
+                        "removed" => (row[i].as_str(), ""),
+                        _ => ("", row[i].as_str()),
+                    };
+                    push(change, self.key_of(row), &self.columns[i], old, new);
+                }
+            }
+        }
+        for row in &self.modified {
+            for change in &row.changes {
+                push(
+                    "modified",
+                    row.key.clone(),
+                    &change.column,
+                    &change.old,
+                    &change.new,
+                );
+            }
+        }
+
+        let width = headers.len();
+        CsvData {
+            data,
+            dialect: Dialect::new(delimiter),
+            line_width: width,
+            headers: Some(headers),
+        }
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} added, {} removed, {} modified",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        )?;
+        for row in &self.removed {
+            writeln!(
+                f,
+                "- {}",
+                format_record(row, &Dialect::default(), QuoteStyle::Minimal)
+            )?;
+        }
+        for row in &self.added {
+            writeln!(
+                f,
+                "+ {}",
+                format_record(row, &Dialect::default(), QuoteStyle::Minimal)
+            )?;
+        }
+        for row in &self.modified {
+            let key = format_record(&row.key, &Dialect::default(), QuoteStyle::Minimal);
+            let changes: Vec<String> = row
+                .changes
+                .iter()
+                .map(|c| format!("{}: {:?} -> {:?}", c.column, c.old, c.new))
+                .collect();
+            writeln!(f, "~ {}: {}", key, changes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl CsvData {
+    /// Compares `self`, the old version of a table, with `new`, pairing rows
+    /// by `keys`. When both tables have headers the other columns are matched
+    /// by name, otherwise by position. A key that occurs several times on a
+    /// side is paired up in order, the surplus counting as added or removed.
+    pub fn diff_by_key(&self, new: &CsvData, keys: &[Column]) -> Result<DiffReport, CsvError> {
+        check_dialects(self, new)?;
+        let old_keys = self.column_indexes(keys)?;
+        let new_keys = new.column_indexes(keys)?;
+        let columns = self.diff_columns(new);
+
+        let old_rows = KeyedRows::new(self, &old_keys);
+        let new_rows = KeyedRows::new(new, &new_keys);
+        let old_row = |row: &[String]| -> Vec<String> {
+            columns
+                .iter()
+                .map(|c| DiffColumn::cell(c.old, row))
+                .collect()
+        };
+        let new_row = |row: &[String]| -> Vec<String> {
+            columns
+                .iter()
+                .map(|c| DiffColumn::cell(c.new, row))
+                .collect()
+        };
+
+        let mut removed = Vec::new();
+        let mut modified = Vec::new();
+        let mut seen = HashSet::new();
+        for (key, _) in &old_rows.rows {
+            if !seen.insert(key) {
+                continue;
+            }
+            let before = &old_rows.groups[key];
+            let after = new_rows.groups.get(key).map_or(&[][..], |rows| &rows[..]);
+            for (old, new) in before.iter().zip(after) {
+                let changes: Vec<CellChange> = columns
+                    .iter()
+                    .map(|c| CellChange {
+                        column: c.name.clone(),
+                        old: DiffColumn::cell(c.old, old),
+                        new: DiffColumn::cell(c.new, new),
+                    })
+                    .filter(|change| change.old != change.new)
+                    .collect();
+                if !changes.is_empty() {
+                    modified.push(ModifiedRow {
+                        key: key.clone(),
+                        changes,
+                    });
+                }
+            }
+            removed.extend(before.iter().skip(after.len()).map(|row| old_row(row)));
+        }
+
+        let mut added = Vec::new();
+        let mut seen: HashMap<&Vec<String>, usize> = HashMap::new();
+        for (key, row) in &new_rows.rows {
+            let count = seen.entry(key).or_default();
+            if *count >= old_rows.groups.get(key).map_or(0, Vec::len) {
+                added.push(new_row(row));
+            }
+            *count += 1;
+        }
+
+        Ok(DiffReport {
+            columns: columns.into_iter().map(|c| c.name).collect(),
+            key_columns: old_keys,
+            added,
+            removed,
+            modified,
+        })
+    }
+
+    fn diff_columns(&self, new: &CsvData) -> Vec<DiffColumn> {
+        match (&self.headers, &new.headers) {
+            (Some(old_headers), Some(new_headers)) => {
+                let mut columns: Vec<DiffColumn> = old_headers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| DiffColumn {
+                        name: name.clone(),
+                        old: Some(i),
+                        new: new.column_index(name),
+                    })
+                    .collect();
+                columns.extend(
+                    new_headers
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, name)| self.column_index(name).is_none())
+                        .map(|(i, name)| DiffColumn {
+                            name: name.clone(),
+                            old: None,
+                            new: Some(i),
+                        }),
+                );
+                columns
+            }
+            _ => (0..self.line_width.max(new.line_width))
+                .map(|i| DiffColumn {
+                    name: i.to_string(),
+                    old: Some(i).filter(|i| *i < self.line_width),
+                    new: Some(i).filter(|i| *i < new.line_width),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::Column;
+    use crate::csvdata::CsvData;
+    use crate::diff::{CellChange, ModifiedRow};
+
+    fn old() -> CsvData {
+        CsvData::from_text_with_headers("id,name,qty\n1,a,5\n2,b,6\n3,c,7".to_string(), ',')
+            .unwrap()
+    }
+
+    fn new() -> CsvData {
+        CsvData::from_text_with_headers("qty,id,name\n5,1,a\n9,2,bee\n1,4,d".to_string(), ',')
+            .unwrap()
+    }
+
+    fn strings(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff_by_key() {
+        let report = old().diff_by_key(&new(), &[Column::from("id")]).unwrap();
+        assert_eq!(report.columns, strings(&["id", "name", "qty"]));
+        assert_eq!(report.removed, vec![strings(&["3", "c", "7"])]);
+        assert_eq!(report.added, vec![strings(&["4", "d", "1"])]);
+        assert_eq!(
+            report.modified,
+            vec![ModifiedRow {
+                key: strings(&["2"]),
+                changes: vec![
+                    CellChange {
+                        column: "name".to_string(),
+                        old: "b".to_string(),
+                        new: "bee".to_string(),
+                    },
+                    CellChange {
+                        column: "qty".to_string(),
+                        old: "6".to_string(),
+                        new: "9".to_string(),
+                    },
+                ],
+            }]
+        );
+        assert!(!report.is_empty());
+        assert!(old()
+            .diff_by_key(&old(), &[Column::Index(0)])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_diff_duplicate_keys_and_positions() {
+        let old = CsvData::from_text("1,a\n1,b\n2,c".to_string(), ',').unwrap();
+        let new = CsvData::from_text("1,a\n2,c,x\n2,d".to_string(), ',').unwrap();
+        let report = old.diff_by_key(&new, &[Column::Index(0)]).unwrap();
+        assert_eq!(report.columns, strings(&["0", "1", "2"]));
+        assert_eq!(report.removed, vec![strings(&["1", "b", ""])]);
+        assert_eq!(report.added, vec![strings(&["2", "d", ""])]);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].changes[0].column, "2");
+    }
+
+    #[test]
+    fn test_diff_report_rendering() {
+        let report = old().diff_by_key(&new(), &[Column::from("id")]).unwrap();
+        assert_eq!(
+            report.to_string(),
+            "1 added, 1 removed, 1 modified\n\
+             - 3,c,7\n\
+             + 4,d,1\n\
+             ~ 2: name: \"b\" -> \"bee\", qty: \"6\" -> \"9\"\n"
+        );
+
+        let csv = report.to_csv(',');
+        assert_eq!(
+            csv.headers().unwrap(),
+            strings(&["change", "id", "column", "old", "new"])
+        );
+        assert_eq!(
+            csv.into_iter().collect::<Vec<_>>(),
+            vec![
+                strings(&["removed", "3", "name", "c", ""]),
+                strings(&["removed", "3", "qty", "7", ""]),
+                strings(&["added", "4", "name", "", "d"]),
+                strings(&["added", "4", "qty", "", "1"]),
+                strings(&["modified", "2", "name", "b", "bee"]),
+                strings(&["modified", "2", "qty", "6", "9"]),
+            ]
+        );
+    }
+}
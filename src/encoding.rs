@@ -0,0 +1,348 @@
+//! Character encodings of CSV files other than plain UTF-8.
+//!
+//! Tables are always held as Unicode text; the encoding only matters on the
+//! way in and out. [`detect_encoding`] tells the encodings apart by a byte
+//! order mark first, then by the zero bytes UTF-16 puts next to ASCII text,
+//! and takes anything that is not valid UTF-8 to be Windows-1252, the usual
+//! encoding of exports from legacy systems.
+
+use std::fmt;
+
+use crate::error::CsvError;
+
+/// Characters of Windows-1252 bytes `0x80` to `0x9F`, where it differs from
+/// Latin-1. The five bytes it leaves undefined map to the C1 controls, as in
+/// Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+const BOM: char = '\u{FEFF}';
+
+/// How the characters of a file are stored as bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1: every byte is the character with the same code point.
+    Latin1,
+    /// Latin-1 with printable characters such as `€` and curly quotes in
+    /// place of most C1 controls, as written by Excel on Windows.
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// The byte order mark of the encoding, empty for the single-byte ones
+    /// that have none.
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Latin1 | Encoding::Windows1252 => b"",
+        }
+    }
+
+    /// Decodes a whole input, dropping a leading byte order mark.
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, CsvError> {
+        let mut decoder = Decoder::new(*self);
+        let mut text = String::new();
+        decoder.decode(bytes, &mut text)?;
+        decoder.finish()?;
+        Ok(text)
+    }
+
+    /// Encodes `text`, without a byte order mark. Fails on the first
+    /// character the encoding cannot represent.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, CsvError> {
+        let unencodable = |character| CsvError::Unencodable {
+            encoding: *self,
+            character,
+        };
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| unencodable(c)))
+                .collect(),
+            Encoding::Windows1252 => text
+                .chars()
+                .map(|c| match u8::try_from(c) {
+                    Ok(byte) if !(0x80..0xA0).contains(&byte) => Ok(byte),
+                    _ => WINDOWS_1252_HIGH
+                        .iter()
+                        .position(|&high| high == c)
+                        .map(|i| 0x80 + i as u8)
+                        .ok_or_else(|| unencodable(c)),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Guesses the encoding of the input `sample` starts with: the one named by
+/// its byte order mark, else UTF-16 if every other byte is mostly zero,
+/// else UTF-8 if it is valid UTF-8, and Windows-1252 otherwise. A character
+/// cut off at the end of the sample does not count against UTF-8.
+pub fn detect_encoding(sample: &[u8]) -> Encoding {
+    for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if sample.starts_with(encoding.bom()) {
+            return encoding;
+        }
+    }
+
+    let units = sample.len() / 2;
+    let (mut even, mut odd) = (0, 0);
+    for pair in sample.chunks_exact(2) {
+        even += usize::from(pair[0] == 0);
+        odd += usize::from(pair[1] == 0);
+    }
+    if odd * 2 > units && even * 8 <= units {
+        return Encoding::Utf16Le;
+    }
+    if even * 2 > units && odd * 8 <= units {
+        return Encoding::Utf16Be;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => Encoding::Utf8,
+        Err(e) if e.error_len().is_none() => Encoding::Utf8,
+        Err(_) => Encoding::Windows1252,
+    }
+}
+
+/// Decodes an input that arrives in chunks. A character split between two
+/// chunks is held back until the rest of it arrives.
+#[derive(Debug, Clone)]
+pub(crate) struct Decoder {
+    encoding: Encoding,
+    pending: Vec<u8>,
+    /// Bytes of the input decoded so far.
+    offset: usize,
+    /// Whether those bytes were all ASCII.
+    ascii: bool,
+    at_start: bool,
+}
+
+impl Decoder {
+    pub(crate) fn new(encoding: Encoding) -> Self {
+        Decoder {
+            encoding,
+            pending: Vec::new(),
+            offset: 0,
+            ascii: true,
+            at_start: true,
+        }
+    }
+
+    /// Switches a UTF-8 decoder that has only decoded ASCII so far, which
+    /// reads the same in Windows-1252, to Windows-1252. Bytes held back,
+    /// including the ones a failed [`decode`](Decoder::decode) stopped at,
+    /// are decoded by the next call. Returns whether it switched.
+    pub(crate) fn fall_back(&mut self) -> bool {
+        let switch = self.encoding == Encoding::Utf8 && self.ascii;
+        if switch {
+            self.encoding = Encoding::Windows1252;
+        }
+        switch
+    }
+
+    /// Appends the characters `bytes` completes to `text`. On an error,
+    /// everything before the offending bytes has still been appended.
+    pub(crate) fn decode(&mut self, bytes: &[u8], text: &mut String) -> Result<(), CsvError> {
+        self.pending.extend_from_slice(bytes);
+        let start = text.len();
+        let (used, invalid) = match self.encoding {
+            Encoding::Utf8 => match std::str::from_utf8(&self.pending) {
+                Ok(decoded) => {
+                    text.push_str(decoded);
+                    (self.pending.len(), false)
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    text.extend(std::str::from_utf8(&self.pending[..valid]));
+                    (valid, e.error_len().is_some())
+                }
+            },
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units: Vec<u16> = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|pair| match self.encoding {
+                        Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                // A high surrogate at the end may be completed by the next
+                // chunk.
+                let complete = match units.last() {
+                    Some(0xD800..=0xDBFF) => units.len() - 1,
+                    _ => units.len(),
+                };
+                let mut decoded = 0;
+                let mut invalid = false;
+                for c in char::decode_utf16(units[..complete].iter().copied()) {
+                    match c {
+                        Ok(c) => {
+                            text.push(c);
+                            decoded += c.len_utf16();
+                        }
+                        Err(_) => {
+                            invalid = true;
+                            break;
+                        }
+                    }
+                }
+                (decoded * 2, invalid)
+            }
+            Encoding::Latin1 => {
+                text.extend(self.pending.iter().map(|&byte| char::from(byte)));
+                (self.pending.len(), false)
+            }
+            Encoding::Windows1252 => {
+                text.extend(self.pending.iter().map(|&byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+                    _ => char::from(byte),
+                }));
+                (self.pending.len(), false)
+            }
+        };
+
+        if self.at_start && text.len() > start {
+            self.at_start = false;
+            if text[start..].starts_with(BOM) {
+                text.replace_range(start..start + BOM.len_utf8(), "");
+            }
+        }
+        self.ascii &= self.pending[..used].is_ascii();
+        self.pending.drain(..used);
+        self.offset += used;
+        match invalid {
+            true => Err(self.invalid()),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that the input did not end in the middle of a character.
+    pub(crate) fn finish(&mut self) -> Result<(), CsvError> {
+        match self.pending.is_empty() {
+            true => Ok(()),
+            false => Err(self.invalid()),
+        }
+    }
+
+    fn invalid(&self) -> CsvError {
+        CsvError::Decode {
+            encoding: self.encoding,
+            offset: self.offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::{detect_encoding, Decoder, Encoding};
+    use crate::error::CsvError;
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFa,b"), Encoding::Utf8);
+        assert_eq!(detect_encoding(b"\xFF\xFEa\0"), Encoding::Utf16Le);
+        assert_eq!(detect_encoding(b"\xFE\xFF\0a"), Encoding::Utf16Be);
+        assert_eq!(detect_encoding(b"a\0,\0b\0"), Encoding::Utf16Le);
+        assert_eq!(detect_encoding(b"\0a\0,\0b"), Encoding::Utf16Be);
+        assert_eq!(detect_encoding("caf\u{e9},1".as_bytes()), Encoding::Utf8);
+        // Cut off in the middle of the `é`.
+        assert_eq!(detect_encoding(b"caf\xC3"), Encoding::Utf8);
+        assert_eq!(detect_encoding(b"caf\xE9,1"), Encoding::Windows1252);
+        assert_eq!(detect_encoding(b""), Encoding::Utf8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "caf\u{e9};\u{20ac}5;\u{201c}q\u{201d}";
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(encoding.decode(&bytes).unwrap(), text);
+            let with_bom = [encoding.bom(), &bytes].concat();
+            assert_eq!(encoding.decode(&with_bom).unwrap(), text);
+            assert_eq!(detect_encoding(&with_bom), encoding);
+        }
+        let bytes = Encoding::Windows1252.encode(text).unwrap();
+        assert_eq!(bytes, b"caf\xE9;\x805;\x93q\x94");
+        assert_eq!(Encoding::Windows1252.decode(&bytes).unwrap(), text);
+        assert_eq!(
+            Encoding::Latin1.decode(&bytes).unwrap(),
+            "caf\u{e9};\u{80}5;\u{93}q\u{94}"
+        );
+        assert!(matches!(
+            Encoding::Latin1.encode(text),
+            Err(CsvError::Unencodable {
+                character: '\u{20ac}',
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decoder_chunks() {
+        // An `é`, a `😀` as a surrogate pair and a BOM, split across chunks.
+        let text = "\u{feff}\u{e9}\u{1f600}";
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = encoding.encode(text).unwrap();
+            let mut decoder = Decoder::new(encoding);
+            let mut decoded = String::new();
+            for byte in &bytes {
+                decoder
+                    .decode(std::slice::from_ref(byte), &mut decoded)
+                    .unwrap();
+            }
+            decoder.finish().unwrap();
+            assert_eq!(decoded, "\u{e9}\u{1f600}");
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(
+            Encoding::Utf8.decode(b"ab\xFFc"),
+            Err(CsvError::Decode { offset: 2, .. })
+        ));
+        assert!(matches!(
+            Encoding::Utf8.decode(b"ab\xC3"),
+            Err(CsvError::Decode { offset: 2, .. })
+        ));
+        // An unpaired low surrogate.
+        assert!(matches!(
+            Encoding::Utf16Le.decode(b"a\0\x00\xDC"),
+            Err(CsvError::Decode { offset: 2, .. })
+        ));
+        assert!(matches!(
+            Encoding::Utf16Be.decode(b"\0a\0"),
+            Err(CsvError::Decode { offset: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use crate::dialect::Dialect;
+use crate::encoding::Encoding;
+
+/// Everything that can go wrong while reading, combining or writing tables.
+#[derive(Debug)]
+pub enum CsvError {
+    /// Two tables that were combined use different delimiters.
+    DelimiterMismatch {
+        left: char,
+        right: char,
+    },
+    /// Two tables that were combined are written in different dialects.
+    /// Convert one with [`CsvData::with_dialect`](crate::CsvData::with_dialect)
+    /// first.
+    DialectMismatch {
+        left: Dialect,
+        right: Dialect,
+    },
+    /// Two tables that were combined both have a header row, and the two
+    /// differ.
+    HeaderMismatch {
+        left: Vec<String>,
+        right: Vec<String>,
+    },
+    /// An operation that needs at least one row, column or table got none.
+    EmptyInput,
+    /// A row is wider than the width it has to fit in, or the last row of
+    /// cells given to [`CsvData::new`](crate::CsvData::new) is cut short.
+    /// `line` is 1-based and counts the header row, if any.
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    Io(std::io::Error),
+    /// Bytes that are not valid in the encoding the input is read in.
+    /// `offset` is the 0-based byte offset of the first of them.
+    Decode {
+        encoding: Encoding,
+        offset: usize,
+    },
+    /// A character the output encoding cannot represent.
+    Unencodable {
+        encoding: Encoding,
+        character: char,
+    },
+    /// Malformed quoting. `line` and `column` are 1-based and point at the
+    /// offending character.
+    Parse {
+        line: usize,
+        column: usize,
+    },
+    /// A column name that is not in the header row, or an index past the
+    /// last column.
+    UnknownColumn {
+        column: String,
+    },
+    /// Rows with this key differ between the two tables of a keyed set
+    /// operation run with [`ConflictPolicy::Error`](crate::ConflictPolicy::Error).
+    KeyConflict {
+        key: Vec<String>,
+    },
+    /// An input that has to be sorted by its key is not: the row at `line`
+    /// sorts before the one above it. `line` is 1-based and counts the
+    /// header row, if any.
+    Unsorted {
+        line: usize,
+    },
+    /// A filter expression that does not parse. `position` is the 1-based
+    /// character position of the offending token.
+    Expression {
+        position: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::DelimiterMismatch { left, right } => {
+                write!(f, "delimiter mismatch: {:?} and {:?}", left, right)
+            }
+            CsvError::DialectMismatch { left, right } => {
+                write!(f, "dialect mismatch: {:?} and {:?}", left, right)
+            }
+            CsvError::HeaderMismatch { left, right } => {
+                write!(f, "header mismatch: {:?} and {:?}", left, right)
+            }
+            CsvError::EmptyInput => write!(f, "empty input"),
+            CsvError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {} has {} fields, expected at most {}",
+                line, found, expected
+            ),
+            CsvError::Io(e) => write!(f, "{}", e),
+            CsvError::Decode { encoding, offset } => {
+                write!(f, "invalid {} at byte {}", encoding, offset)
+            }
+            CsvError::Unencodable {
+                encoding,
+                character,
+            } => write!(f, "{:?} cannot be written in {}", character, encoding),
+            CsvError::Parse { line, column } => {
+                write!(f, "malformed quoting at line {}, column {}", line, column)
+            }
+            CsvError::UnknownColumn { column } => write!(f, "unknown column {}", column),
+            CsvError::KeyConflict { key } => {
+                write!(f, "conflicting rows for key {:?}", key)
+            }
+            CsvError::Unsorted { line } => {
+                write!(f, "input is not sorted by its key at line {}", line)
+            }
+            CsvError::Expression { position, message } => {
+                write!(
+                    f,
+                    "invalid expression at position {}: {}",
+                    position, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CsvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CsvError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CsvError {
+    fn from(e: std::io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
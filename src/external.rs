@@ -0,0 +1,333 @@
+//! Sorting inputs that do not fit in memory.
+//!
+//! Rows are collected until they reach the memory budget, sorted and
+//! written to a temporary file as a run. Once the input is exhausted the
+//! runs are merged, one row of each held in memory at a time. Only a
+//! fixed number of runs are open at once: when there are more, consecutive
+//! groups of them are first merged into longer runs, in as many passes as
+//! it takes. Inputs that fit within the budget are sorted in memory without
+//! touching the disk.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::iter;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Arc;
+use std::vec;
+
+use crate::encoding::Encoding;
+use crate::error::CsvError;
+use crate::reader::{CsvReader, ReaderBuilder};
+use crate::sort::{RowComparator, SortKey};
+use crate::writer::WriterBuilder;
+
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Most runs merged, and so files held open, at a time.
+const MERGE_FAN_IN: usize = 64;
+
+/// Numbers the run files of this process, so that concurrent sorts never
+/// share a file.
+static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+
+/// An external merge sort on the same keys as [`CsvData::sort_by`](crate::CsvData::sort_by).
+/// Like it, the sort is stable.
+#[derive(Debug, Clone)]
+pub struct ExternalSort {
+    keys: Vec<SortKey>,
+    memory_budget: usize,
+    temp_dir: PathBuf,
+}
+
+impl ExternalSort {
+    /// A sort on `keys` with a 64 MiB budget that spills to the system's
+    /// temporary directory.
+    pub fn new(keys: Vec<SortKey>) -> Self {
+        ExternalSort {
+            keys,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Approximate number of bytes of rows to hold before spilling a run.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    /// Sorts `rows`, resolving key names against `headers`. The header row
+    /// itself is not part of `rows`. Fails on the first error from `rows`
+    /// or from writing a run; errors reading runs back come out of the
+    /// returned iterator.
+    pub fn sort<I>(&self, headers: Option<&[String]>, rows: I) -> Result<SortedRows, CsvError>
+    where
+        I: IntoIterator<Item = Result<Vec<String>, CsvError>>,
+    {
+        let comparator = Arc::new(RowComparator::new(&self.keys, |column| {
+            column.resolve(headers)
+        })?);
+        let mut runs = Vec::new();
+        let mut chunk = Vec::new();
+        let mut used = 0;
+        for row in rows {
+            let row = row?;
+            used += row_size(&row);
+            chunk.push(row);
+            if used >= self.memory_budget {
+                runs.push(self.spill(&mut chunk, &comparator)?);
+                used = 0;
+            }
+        }
+
+        if runs.is_empty() {
+            chunk.sort_by(|a, b| comparator.compare(a, b));
+            return Ok(SortedRows {
+                inner: Inner::Memory(chunk.into_iter()),
+            });
+        }
+        if !chunk.is_empty() {
+            runs.push(self.spill(&mut chunk, &comparator)?);
+        }
+        // Merging neighbouring runs, and keeping the merged runs in the
+        // same order, keeps the sort stable.
+        while runs.len() > MERGE_FAN_IN {
+            let mut merged = Vec::new();
+            let mut rest = runs.into_iter();
+            loop {
+                let group: Vec<Run> = rest.by_ref().take(MERGE_FAN_IN).collect();
+                match group.len() {
+                    0 => break,
+                    1 => merged.extend(group),
+                    _ => {
+                        let mut merge = Merge::new(group, &comparator)?;
+                        merged.push(self.write_run(iter::from_fn(|| merge.next_row()))?);
+                    }
+                }
+            }
+            runs = merged;
+        }
+        Ok(SortedRows {
+            inner: Inner::Merge(Merge::new(runs, &comparator)?),
+        })
+    }
+
+    /// Sorts `chunk` and writes it to a new run file, leaving it empty.
+    fn spill(
+        &self,
+        chunk: &mut Vec<Vec<String>>,
+        comparator: &RowComparator,
+    ) -> Result<Run, CsvError> {
+        chunk.sort_by(|a, b| comparator.compare(a, b));
+        self.write_run(chunk.drain(..).map(Ok))
+    }
+
+    /// Writes `rows` to a new run file.
+    fn write_run<I>(&self, rows: I) -> Result<Run, CsvError>
+    where
+        I: Iterator<Item = Result<Vec<String>, CsvError>>,
+    {
+        let run = Run {
+            path: self.temp_dir.join(format!(
+                "rcsvtools-sort-{}-{}.csv",
+                process::id(),
+                NEXT_RUN.fetch_add(1, atomic::Ordering::Relaxed)
+            )),
+        };
+        // The reader drops this byte order mark and no other, so a first
+        // cell that starts with U+FEFF reads back unchanged.
+        let mut writer = WriterBuilder::new()
+            .bom(true)
+            .build(File::create(&run.path)?);
+        for row in rows {
+            writer.write_record(&row?)?;
+        }
+        writer.flush()?;
+        Ok(run)
+    }
+}
+
+/// Rough heap footprint of a row.
+fn row_size(row: &[String]) -> usize {
+    size_of::<Vec<String>>()
+        + row
+            .iter()
+            .map(|cell| size_of::<String>() + cell.len())
+            .sum::<usize>()
+}
+
+/// A temporary run file, removed when dropped.
+struct Run {
+    path: PathBuf,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads a run back. The reader is declared first so that the file is
+/// closed before it is removed.
+struct RunReader {
+    reader: CsvReader<File>,
+    _run: Run,
+}
+
+/// The next row of a run, ordered so that the [`BinaryHeap`] of a [`Merge`]
+/// pops the smallest row first and, among equal rows, the one from the
+/// earliest run.
+struct Head {
+    row: Vec<String>,
+    run: usize,
+    comparator: Arc<RowComparator>,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator
+            .compare(&other.row, &self.row)
+            .then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head {}
+
+/// Merges sorted runs, holding the next row of each in a heap.
+struct Merge {
+    readers: Vec<RunReader>,
+    heads: BinaryHeap<Head>,
+}
+
+impl Merge {
+    fn new(runs: Vec<Run>, comparator: &Arc<RowComparator>) -> Result<Self, CsvError> {
+        let mut readers = Vec::new();
+        let mut heads = BinaryHeap::new();
+        for (i, run) in runs.into_iter().enumerate() {
+            let mut reader = ReaderBuilder::new()
+                .encoding(Encoding::Utf8)
+                .open(&run.path)?;
+            if let Some(row) = reader.next().transpose()? {
+                heads.push(Head {
+                    row,
+                    run: i,
+                    comparator: Arc::clone(comparator),
+                });
+            }
+            readers.push(RunReader { reader, _run: run });
+        }
+        Ok(Merge { readers, heads })
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<String>, CsvError>> {
+        let head = self.heads.pop()?;
+        match self.readers[head.run].reader.next() {
+            Some(Ok(row)) => self.heads.push(Head {
+                row,
+                run: head.run,
+                comparator: Arc::clone(&head.comparator),
+            }),
+            Some(Err(e)) => return Some(Err(e)),
+            None => {}
+        }
+        Some(Ok(head.row))
+    }
+}
+
+enum Inner {
+    Memory(vec::IntoIter<Vec<String>>),
+    Merge(Merge),
+}
+
+/// The rows of an [`ExternalSort`] in order. The run files are removed when
+/// this is dropped.
+pub struct SortedRows {
+    inner: Inner,
+}
+
+impl Iterator for SortedRows {
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            Inner::Memory(rows) => rows.next().map(Ok),
+            Inner::Merge(merge) => merge.next_row(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+    use crate::external::ExternalSort;
+    use crate::sort::{Comparison, SortKey, SortOrder};
+
+    fn rows(csv: &CsvData) -> Vec<Result<Vec<String>, CsvError>> {
+        csv.clone().into_iter().map(Ok).collect()
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory_sort() {
+        let dir = std::env::temp_dir().join(format!("rcsvtools-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut text: String = (0..200)
+            .map(|i| format!("{},\"x\n{}\",{}\n", (i * 37) % 50, i, i % 3))
+            .collect();
+        // Run files must not lose a leading U+FEFF of the first cell.
+        text.push_str("\u{feff}b,1,1\n\u{feff}a,2,1\n");
+        let csv = CsvData::from_text_with_headers(format!("k,v,g\n{}", text), ',').unwrap();
+        let keys = vec![
+            SortKey::new("g").order(SortOrder::Descending),
+            SortKey::new(0).comparison(Comparison::Numeric),
+        ];
+        let expected: Vec<Vec<String>> = csv.sort_by(&keys).unwrap().into_iter().collect();
+
+        // A budget of 1 spills every row, more runs than one pass merges.
+        for budget in [1, 1000, usize::MAX] {
+            let sorted = ExternalSort::new(keys.clone())
+                .memory_budget(budget)
+                .temp_dir(&dir)
+                .sort(csv.headers(), rows(&csv))
+                .unwrap();
+            let result: Vec<Vec<String>> = sorted.map(Result::unwrap).collect();
+            assert_eq!(result, expected, "budget {}", budget);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_external_sort_errors() {
+        let sort = ExternalSort::new(vec![SortKey::new("k")]);
+        let result = sort.sort(None, Vec::new());
+        assert!(matches!(result, Err(CsvError::UnknownColumn { .. })));
+
+        let sort = ExternalSort::new(vec![SortKey::new(0)]);
+        let input = vec![Ok(vec!["a".to_string()]), Err(CsvError::EmptyInput)];
+        assert!(matches!(sort.sort(None, input), Err(CsvError::EmptyInput)));
+    }
+}
@@ -0,0 +1,596 @@
+//! The expression language of [`CsvData::filter_expr`].
+
+use std::cmp::Ordering;
+
+use crate::csvdata::CsvData;
+use crate::error::CsvError;
+use crate::regex::Regex;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum TokenKind {
+    Name(String),
+    Index(usize),
+    Text(String),
+    Number(String),
+    Null,
+    And,
+    Or,
+    Not,
+    Is,
+    Compare(CompareOp),
+    Match { negated: bool },
+    Open,
+    Close,
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// 1-based character position of the first character.
+    position: usize,
+    text: String,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self.kind {
+            TokenKind::End => "end of expression".to_string(),
+            _ => format!("`{}`", self.text),
+        }
+    }
+
+    /// Offset from the token's first character to the source character that
+    /// became character `index` of its unquoted text, counting a doubled
+    /// quote as two.
+    fn source_offset(&self, index: usize) -> usize {
+        let mut chars = self.text.chars();
+        let quote = chars.next();
+        let mut offset = 1;
+        for _ in 0..index {
+            match chars.next() {
+                Some(c) if Some(c) == quote => {
+                    chars.next();
+                    offset += 2;
+                }
+                Some(_) => offset += 1,
+                None => break,
+            }
+        }
+        offset
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Cell(usize),
+    Literal(String),
+    Number(String),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Value, CompareOp, Value),
+    Match {
+        value: Value,
+        regex: Regex,
+        negated: bool,
+    },
+    IsNull {
+        value: Value,
+        negated: bool,
+    },
+}
+
+/// A parsed filter expression with its columns resolved, ready to test
+/// rows.
+///
+/// ```text
+/// status == 'active' and amount > 100
+/// not (name =~ '^a' or #3 is null)
+/// ```
+///
+/// Columns are header names, `` `quoted names` `` or `#N` for the 0-based
+/// position. Literals are numbers, strings in single or double quotes, in
+/// which a doubled quote stands for itself, and `null`, the empty cell.
+///
+/// Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) are numeric when both sides
+/// parse as numbers and compare strings otherwise. Against a number literal
+/// they are always numeric: a cell that is not a number is then only
+/// different (`!=`) from it. `=~` and `!~` look for a regular expression
+/// anywhere in the cell, `is null` and `is not null` test for an empty cell.
+/// `not`, `and` and `or` (or `!`, `&&` and `||`) combine them, `not` binding
+/// tightest and `or` loosest.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    node: Node,
+}
+
+fn error(position: usize, message: String) -> CsvError {
+    CsvError::Expression { position, message }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, CsvError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        let kind = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::Open
+            }
+            ')' => {
+                i += 1;
+                TokenKind::Close
+            }
+            '\'' | '"' | '`' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            let message = "unterminated quote".to_string();
+                            return Err(error(start + 1, message));
+                        }
+                        Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                            text.push(c);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            text.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                match c {
+                    '`' => TokenKind::Name(text),
+                    _ => TokenKind::Text(text),
+                }
+            }
+            '#' => {
+                i += 1;
+                while chars.get(i).is_some_and(char::is_ascii_digit) {
+                    i += 1;
+                }
+                let digits: String = chars[start + 1..i].iter().collect();
+                match digits.parse() {
+                    Ok(index) => TokenKind::Index(index),
+                    Err(_) => {
+                        let message = "expected a column number after `#`".to_string();
+                        return Err(error(start + 1, message));
+                    }
+                }
+            }
+            _ if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) =>
+            {
+                i += 1;
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                if number.parse::<f64>().is_err() {
+                    let message = format!("malformed number `{}`", number);
+                    return Err(error(start + 1, message));
+                }
+                TokenKind::Number(number)
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.to_lowercase().as_str() {
+                    "and" => TokenKind::And,
+                    "or" => TokenKind::Or,
+                    "not" => TokenKind::Not,
+                    "is" => TokenKind::Is,
+                    "null" => TokenKind::Null,
+                    _ => TokenKind::Name(word),
+                }
+            }
+            _ => {
+                let (kind, len) = match two.as_str() {
+                    "==" => (TokenKind::Compare(CompareOp::Eq), 2),
+                    "!=" | "<>" => (TokenKind::Compare(CompareOp::Ne), 2),
+                    "<=" => (TokenKind::Compare(CompareOp::Le), 2),
+                    ">=" => (TokenKind::Compare(CompareOp::Ge), 2),
+                    "=~" => (TokenKind::Match { negated: false }, 2),
+                    "!~" => (TokenKind::Match { negated: true }, 2),
+                    "&&" => (TokenKind::And, 2),
+                    "||" => (TokenKind::Or, 2),
+                    _ => match c {
+                        '=' => (TokenKind::Compare(CompareOp::Eq), 1),
+                        '<' => (TokenKind::Compare(CompareOp::Lt), 1),
+                        '>' => (TokenKind::Compare(CompareOp::Gt), 1),
+                        '!' => (TokenKind::Not, 1),
+                        _ => {
+                            let message = format!("unexpected character `{}`", c);
+                            return Err(error(start + 1, message));
+                        }
+                    },
+                };
+                i += len;
+                kind
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start + 1,
+            text: chars[start..i].iter().collect(),
+        });
+    }
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: chars.len() + 1,
+        text: String::new(),
+    });
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    headers: Option<&'a [String]>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> CsvError {
+        let token = self.peek();
+        let message = format!("expected {}, found {}", expected, token.describe());
+        error(token.position, message)
+    }
+
+    fn or(&mut self) -> Result<Node, CsvError> {
+        let mut node = self.and()?;
+        while self.peek().kind == TokenKind::Or {
+            self.next();
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, CsvError> {
+        let mut node = self.unary()?;
+        while self.peek().kind == TokenKind::And {
+            self.next();
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, CsvError> {
+        match self.peek().kind {
+            TokenKind::Not => {
+                self.next();
+                Ok(Node::Not(Box::new(self.unary()?)))
+            }
+            TokenKind::Open => {
+                self.next();
+                let node = self.or()?;
+                match self.peek().kind {
+                    TokenKind::Close => {
+                        self.next();
+                        Ok(node)
+                    }
+                    _ => Err(self.unexpected("`)`")),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Node, CsvError> {
+        let value = self.value()?;
+        match self.peek().kind {
+            TokenKind::Compare(op) => {
+                self.next();
+                Ok(Node::Compare(value, op, self.value()?))
+            }
+            TokenKind::Match { negated } => {
+                self.next();
+                let token = self.next();
+                let TokenKind::Text(pattern) = &token.kind else {
+                    self.pos -= 1;
+                    return Err(self.unexpected("a quoted regular expression"));
+                };
+                let regex = Regex::new(pattern).map_err(|e| {
+                    let message = format!("invalid regular expression: {}", e.message);
+                    error(token.position + token.source_offset(e.position), message)
+                })?;
+                Ok(Node::Match {
+                    value,
+                    regex,
+                    negated,
+                })
+            }
+            TokenKind::Is => {
+                self.next();
+                let negated = self.peek().kind == TokenKind::Not;
+                if negated {
+                    self.next();
+                }
+                match self.peek().kind {
+                    TokenKind::Null => {
+                        self.next();
+                        Ok(Node::IsNull { value, negated })
+                    }
+                    _ => Err(self.unexpected("`null`")),
+                }
+            }
+            _ => Err(self.unexpected("a comparison")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, CsvError> {
+        let token = self.peek().clone();
+        let value = match &token.kind {
+            TokenKind::Name(name) => {
+                let index = self
+                    .headers
+                    .and_then(|headers| headers.iter().position(|h| h == name));
+                match (index, self.headers) {
+                    (Some(index), _) => Value::Cell(index),
+                    (None, Some(_)) => {
+                        let message = format!("unknown column `{}`", name);
+                        return Err(error(token.position, message));
+                    }
+                    (None, None) => {
+                        let message = format!(
+                            "unknown column `{}`, the input has no header row; use `#N`",
+                            name
+                        );
+                        return Err(error(token.position, message));
+                    }
+                }
+            }
+            TokenKind::Index(index) => Value::Cell(*index),
+            TokenKind::Text(text) => Value::Literal(text.clone()),
+            TokenKind::Number(text) => Value::Number(text.clone()),
+            TokenKind::Null => Value::Literal(String::new()),
+            _ => return Err(self.unexpected("a column or a value")),
+        };
+        self.next();
+        Ok(value)
+    }
+}
+
+fn number(text: &str) -> Option<f64> {
+    text.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+impl Value {
+    fn get<'r, S: AsRef<str>>(&'r self, row: &'r [S]) -> &'r str {
+        match self {
+            Value::Cell(index) => row.get(*index).map_or("", |cell| cell.as_ref()),
+            Value::Literal(text) | Value::Number(text) => text,
+        }
+    }
+}
+
+impl Node {
+    fn eval<S: AsRef<str>>(&self, row: &[S]) -> bool {
+        match self {
+            Node::And(left, right) => left.eval(row) && right.eval(row),
+            Node::Or(left, right) => left.eval(row) || right.eval(row),
+            Node::Not(node) => !node.eval(row),
+            Node::Compare(left, op, right) => {
+                let numeric = matches!(left, Value::Number(_)) || matches!(right, Value::Number(_));
+                let (left, right) = (left.get(row), right.get(row));
+                let ordering = match (number(left), number(right)) {
+                    (Some(left), Some(right)) => left.partial_cmp(&right),
+                    _ if numeric => return *op == CompareOp::Ne,
+                    _ => Some(left.cmp(right)),
+                };
+                match (ordering, op) {
+                    (Some(ordering), CompareOp::Eq) => ordering == Ordering::Equal,
+                    (Some(ordering), CompareOp::Ne) => ordering != Ordering::Equal,
+                    (Some(ordering), CompareOp::Lt) => ordering == Ordering::Less,
+                    (Some(ordering), CompareOp::Le) => ordering != Ordering::Greater,
+                    (Some(ordering), CompareOp::Gt) => ordering == Ordering::Greater,
+                    (Some(ordering), CompareOp::Ge) => ordering != Ordering::Less,
+                    (None, _) => false,
+                }
+            }
+            Node::Match {
+                value,
+                regex,
+                negated,
+            } => regex.is_match(value.get(row)) != *negated,
+            Node::IsNull { value, negated } => value.get(row).is_empty() != *negated,
+        }
+    }
+}
+
+impl Predicate {
+    /// Parses `expression`, resolving column names against `headers`. Fails
+    /// with [`CsvError::Expression`] pointing at the offending token.
+    pub fn parse(expression: &str, headers: Option<&[String]>) -> Result<Predicate, CsvError> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+            headers,
+        };
+        let node = parser.or()?;
+        match parser.peek().kind {
+            TokenKind::End => Ok(Predicate { node }),
+            _ => Err(parser.unexpected("`and`, `or` or the end of the expression")),
+        }
+    }
+
+    /// Whether `row` satisfies the expression. Columns past the end of the
+    /// row read as null.
+    pub fn matches<S: AsRef<str>>(&self, row: &[S]) -> bool {
+        self.node.eval(row)
+    }
+}
+
+impl CsvData {
+    /// The rows for which `expression` holds, in their current order. See
+    /// [`Predicate`] for the syntax.
+    pub fn filter_expr(&self, expression: &str) -> Result<CsvData, CsvError> {
+        let predicate = Predicate::parse(expression, self.headers())?;
+        Ok(CsvData {
+            data: self
+                .rows()
+                .filter(|row| predicate.matches(row))
+                .flatten()
+                .cloned()
+                .collect(),
+            dialect: self.dialect,
+            line_width: self.line_width,
+            headers: self.headers.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+    use crate::filter::Predicate;
+
+    fn accounts() -> CsvData {
+        CsvData::from_text_with_headers(
+            "name,status,amount,note\n\
+             ann,active,150,\n\
+             bob,active,99.5,vip\n\
+             cy,closed,1000,\n\
+             dee,active,20a,x"
+                .to_string(),
+            ',',
+        )
+        .unwrap()
+    }
+
+    fn names(expression: &str) -> Vec<String> {
+        accounts()
+            .filter_expr(expression)
+            .unwrap()
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_expr() {
+        assert_eq!(names("status == 'active' and amount > 100"), ["ann"]);
+        assert_eq!(names("amount >= 99.5 && #1 != \"closed\""), ["ann", "bob"]);
+        // `20a` is not a number: it differs from every number, and compares
+        // as a string against a string.
+        assert_eq!(names("amount != 150"), ["bob", "cy", "dee"]);
+        assert_eq!(names("amount < '3'"), ["dee"]);
+        assert_eq!(
+            names("not (status = 'active' or name =~ '^c')"),
+            Vec::<String>::new()
+        );
+        assert_eq!(names("name !~ '[ab]' AND note is not null"), ["dee"]);
+        assert_eq!(names("note is null or note == null"), ["ann", "cy"]);
+        assert_eq!(
+            names("`status` <> 'active' || amount == 150.0"),
+            ["ann", "cy"]
+        );
+    }
+
+    #[test]
+    fn test_predicate_on_rows() {
+        let predicate = Predicate::parse("#1 == 'it''s' and #5 is null", None).unwrap();
+        assert!(predicate.matches(&["x", "it's"]));
+        assert!(!predicate.matches(&["x", "its"]));
+        let row: Vec<String> = vec!["a".to_string()];
+        assert!(!predicate.matches(&row));
+    }
+
+    #[test]
+    fn test_filter_expr_errors() {
+        for (expression, position, message) in [
+            (
+                "amount >",
+                9,
+                "expected a column or a value, found end of expression",
+            ),
+            (
+                "amount > 1 status",
+                12,
+                "expected `and`, `or` or the end of the expression, found `status`",
+            ),
+            ("(amount > 1", 12, "expected `)`, found end of expression"),
+            (
+                "amount",
+                7,
+                "expected a comparison, found end of expression",
+            ),
+            ("nmae == 'x'", 1, "unknown column `nmae`"),
+            ("name == 'x", 9, "unterminated quote"),
+            ("name $ 'x'", 6, "unexpected character `$`"),
+            (
+                "name =~ 3",
+                9,
+                "expected a quoted regular expression, found `3`",
+            ),
+            (
+                "name =~ 'a(b'",
+                11,
+                "invalid regular expression: unclosed group",
+            ),
+            (
+                "name =~ 'it''s(x'",
+                15,
+                "invalid regular expression: unclosed group",
+            ),
+            ("amount > 1.2.3", 10, "malformed number `1.2.3`"),
+            ("note is 'x'", 9, "expected `null`, found `'x'`"),
+        ] {
+            match accounts().filter_expr(expression) {
+                Err(CsvError::Expression {
+                    position: p,
+                    message: m,
+                }) => assert_eq!((p, m.as_str()), (position, message), "{}", expression),
+                other => panic!("{}: {:?}", expression, other),
+            }
+        }
+    }
+}
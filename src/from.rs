@@ -1,14 +0,0 @@
-use std::fs::File;
-
-
-// fn from_text(file_name :String, delimiter: char, data: Vec<String>,line_width:usize) -> Result<(), Err()> {
-//     let mut file = File::create(file_name);
-//
-//
-//     Ok(());
-//
-//
-//
-//
-//
-// }
\ No newline at end of file
@@ -0,0 +1,299 @@
+//! Grouping rows on key columns and aggregating the other columns.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::column::Column;
+use crate::csvdata::CsvData;
+use crate::error::CsvError;
+use crate::sort::{compare_parsed, parse_number};
+
+/// How the cells of a column are combined into one per group. Empty cells
+/// are null and ignored by every function except `Count`, `First` and
+/// `Last`; `Sum`, `Mean` and `Median` also skip cells that are not numbers.
+/// A function with nothing to combine yields an empty cell.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Aggregate {
+    /// Number of rows in the group.
+    Count,
+    Sum,
+    /// Smallest cell. Numbers compare as numbers and sort before other
+    /// cells, like [`Comparison::Numeric`](crate::Comparison::Numeric).
+    Min,
+    /// Largest cell, compared like for `Min`.
+    Max,
+    Mean,
+    /// Middle number, or the mean of the two middle ones.
+    Median,
+    /// Cell of the first row of the group.
+    First,
+    /// Cell of the last row of the group.
+    Last,
+    /// Number of different cells.
+    CountDistinct,
+    /// Every cell, in input order, joined with the given separator.
+    Concat(String),
+}
+
+impl Aggregate {
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+            Aggregate::First => "first",
+            Aggregate::Last => "last",
+            Aggregate::CountDistinct => "count_distinct",
+            Aggregate::Concat(_) => "concat",
+        }
+    }
+
+    pub(crate) fn apply(&self, cells: &[&str]) -> String {
+        let values = || cells.iter().copied().filter(|cell| !cell.is_empty());
+        let numbers = || values().filter_map(parse_number).collect::<Vec<f64>>();
+        match self {
+            Aggregate::Count => cells.len().to_string(),
+            Aggregate::Sum => {
+                let numbers = numbers();
+                format_number(&numbers, numbers.iter().sum())
+            }
+            Aggregate::Min => values()
+                .min_by(|a, b| compare_parsed(a, b, parse_number))
+                .unwrap_or_default()
+                .to_string(),
+            Aggregate::Max => values()
+                .max_by(|a, b| compare_parsed(a, b, parse_number))
+                .unwrap_or_default()
+                .to_string(),
+            Aggregate::Mean => {
+                let numbers = numbers();
+                let sum: f64 = numbers.iter().sum();
+                format_number(&numbers, sum / numbers.len() as f64)
+            }
+            Aggregate::Median => {
+                let mut numbers = numbers();
+                numbers.sort_by(|a, b| a.total_cmp(b));
+                let middle = numbers.len() / 2;
+                let median = match numbers.len() % 2 {
+                    0 if middle > 0 => (numbers[middle - 1] + numbers[middle]) / 2.0,
+                    0 => 0.0,
+                    _ => numbers[middle],
+                };
+                format_number(&numbers, median)
+            }
+            Aggregate::First => cells.first().copied().unwrap_or_default().to_string(),
+            Aggregate::Last => cells.last().copied().unwrap_or_default().to_string(),
+            Aggregate::CountDistinct => values().collect::<HashSet<_>>().len().to_string(),
+            Aggregate::Concat(separator) => values().collect::<Vec<_>>().join(separator),
+        }
+    }
+}
+
+/// `result`, or an empty cell when there were no `numbers` to compute it
+/// from.
+fn format_number(numbers: &[f64], result: f64) -> String {
+    if numbers.is_empty() {
+        String::new()
+    } else {
+        result.to_string()
+    }
+}
+
+/// One output column of [`GroupBy::agg`]: a function applied to a column. A
+/// range of columns yields one output column per column in it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Aggregation {
+    pub column: Column,
+    pub function: Aggregate,
+    /// Header of the output column. Defaults to the function name and the
+    /// header of the column, as in `sum_amount`.
+    pub alias: Option<String>,
+}
+
+impl Aggregation {
+    pub fn new<C: Into<Column>>(column: C, function: Aggregate) -> Self {
+        Aggregation {
+            column: column.into(),
+            function,
+            alias: None,
+        }
+    }
+
+    pub fn alias<S: Into<String>>(mut self, alias: S) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    fn header(&self, column: &str) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => format!("{}_{}", self.function.name(), column),
+        }
+    }
+}
+
+/// The rows of a table grouped on key columns, returned by
+/// [`CsvData::group_by`].
+#[derive(Debug, Clone)]
+pub struct GroupBy<'a> {
+    csv: &'a CsvData,
+    keys: Vec<Column>,
+}
+
+impl GroupBy<'_> {
+    /// One row per distinct key, in order of first appearance, holding the
+    /// key columns followed by one column per aggregated column. Without
+    /// keys the whole table is a single group, even when it has no rows.
+    /// The result has a header row if the table has one.
+    pub fn agg(&self, aggregations: &[Aggregation]) -> Result<CsvData, CsvError> {
+        let keys = self.csv.column_indexes(&self.keys)?;
+        let mut columns = Vec::new();
+        for aggregation in aggregations {
+            for index in self
+                .csv
+                .column_indexes(std::slice::from_ref(&aggregation.column))?
+            {
+                columns.push((index, aggregation));
+            }
+        }
+
+        let mut positions: HashMap<Vec<&str>, usize> = HashMap::new();
+        let mut groups: Vec<(Vec<&str>, Vec<&[String]>)> = Vec::new();
+        for row in self.csv.rows() {
+            let key: Vec<&str> = keys.iter().map(|&i| row[i].as_str()).collect();
+            let position = *positions.entry(key.clone()).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[position].1.push(row);
+        }
+        if keys.is_empty() && groups.is_empty() {
+            groups.push((Vec::new(), Vec::new()));
+        }
+
+        let mut data = Vec::new();
+        for (key, rows) in &groups {
+            data.extend(key.iter().map(|cell| cell.to_string()));
+            for (index, aggregation) in &columns {
+                let cells: Vec<&str> = rows.iter().map(|row| row[*index].as_str()).collect();
+                data.push(aggregation.function.apply(&cells));
+            }
+        }
+        let headers = self.csv.headers.as_ref().map(|headers| {
+            keys.iter()
+                .map(|&i| headers[i].clone())
+                .chain(
+                    columns
+                        .iter()
+                        .map(|(index, aggregation)| aggregation.header(&headers[*index])),
+                )
+                .collect()
+        });
+
+        Ok(CsvData {
+            data,
+            dialect: self.csv.dialect,
+            line_width: keys.len() + columns.len(),
+            headers,
+        })
+    }
+}
+
+impl CsvData {
+    /// Groups the rows on `keys`, to be aggregated with [`GroupBy::agg`].
+    pub fn group_by(&self, keys: &[Column]) -> GroupBy<'_> {
+        GroupBy {
+            csv: self,
+            keys: keys.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::Column;
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+    use crate::groupby::{Aggregate, Aggregation};
+
+    fn sales() -> CsvData {
+        CsvData::from_text_with_headers(
+            "region;item;amount\nnorth;a;10\nsouth;b;3\nnorth;c;\nnorth;a;2.5\nsouth;b;x"
+                .to_string(),
+            ';',
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_group_by_agg() {
+        let result = sales()
+            .group_by(&[Column::from("region")])
+            .agg(&[
+                Aggregation::new("item", Aggregate::Count),
+                Aggregation::new("amount", Aggregate::Sum),
+                Aggregation::new("amount", Aggregate::Mean).alias("average"),
+                Aggregation::new("amount", Aggregate::Min),
+                Aggregation::new("amount", Aggregate::Max),
+                Aggregation::new("item", Aggregate::CountDistinct),
+                Aggregation::new("item", Aggregate::Concat("|".to_string())),
+            ])
+            .unwrap();
+        assert_eq!(result.delimiter(), ';');
+        assert_eq!(result.line_width, 8);
+        assert_eq!(
+            result.headers().unwrap(),
+            [
+                "region",
+                "count_item",
+                "sum_amount",
+                "average",
+                "min_amount",
+                "max_amount",
+                "count_distinct_item",
+                "concat_item"
+            ]
+        );
+        assert_eq!(
+            result.data,
+            [
+                "north", "3", "12.5", "6.25", "2.5", "10", "2", "a|c|a", //
+                "south", "2", "3", "3", "3", "x", "1", "b|b",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_median_first_last() {
+        let csv = CsvData::from_text("k,4\nk,1\nj,\nk,3\nk,10".to_string(), ',').unwrap();
+        let result = csv
+            .group_by(&[Column::Index(0)])
+            .agg(&[
+                Aggregation::new(1, Aggregate::Median),
+                Aggregation::new(1, Aggregate::First),
+                Aggregation::new(1, Aggregate::Last),
+            ])
+            .unwrap();
+        assert_eq!(result.headers(), None);
+        assert_eq!(result.data, ["k", "3.5", "4", "10", "j", "", "", ""]);
+    }
+
+    #[test]
+    fn test_group_by_without_keys() {
+        let result = sales()
+            .group_by(&[])
+            .agg(&[Aggregation::new(1.., Aggregate::Count)])
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["count_item", "count_amount"]);
+        assert_eq!(result.data, ["5", "5"]);
+
+        assert!(matches!(
+            sales()
+                .group_by(&[Column::from("nope")])
+                .agg(&[Aggregation::new(0, Aggregate::Count)]),
+            Err(CsvError::UnknownColumn { .. })
+        ));
+    }
+}
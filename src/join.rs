@@ -0,0 +1,411 @@
+//! Relational joins of two tables on one or more key columns.
+//!
+//! The result holds every column of the left table followed by the non-key
+//! columns of the right one; semi and anti joins only keep the left columns.
+//! Cells of a side without a matching row are empty, except that the key
+//! columns of a right row with no partner are filled from that row.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::column::Column;
+use crate::csvdata::{check_dialects, CsvData};
+use crate::error::CsvError;
+
+/// Which rows a join keeps.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum JoinKind {
+    /// Pairs of rows whose keys match.
+    #[default]
+    Inner,
+    /// Every left row, paired with its matches if it has any.
+    Left,
+    /// Every right row, paired with its matches if it has any.
+    Right,
+    /// Every row of both tables, paired where the keys match.
+    Full,
+    /// Left rows that have at least one match, once each.
+    Semi,
+    /// Left rows that have no match.
+    Anti,
+}
+
+/// How a join finds matching rows.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum JoinAlgorithm {
+    /// Index the right table by key. Works on any input; the result follows
+    /// the order of the left table, with unmatched right rows at the end.
+    #[default]
+    Hash,
+    /// Walk both tables in step. Both have to be sorted by their key,
+    /// comparing cells as strings, or the join fails with
+    /// [`CsvError::Unsorted`]; the result is in key order.
+    SortMerge,
+}
+
+/// Settings for [`CsvData::join`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct JoinOptions {
+    pub kind: JoinKind,
+    pub algorithm: JoinAlgorithm,
+    /// Appended to the header of a left and a right column that share a
+    /// name.
+    pub suffixes: (String, String),
+}
+
+impl Default for JoinOptions {
+    fn default() -> Self {
+        JoinOptions {
+            kind: JoinKind::default(),
+            algorithm: JoinAlgorithm::default(),
+            suffixes: ("_left".to_string(), "_right".to_string()),
+        }
+    }
+}
+
+impl JoinOptions {
+    pub fn kind(mut self, kind: JoinKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: JoinAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn suffixes<S: Into<String>>(mut self, left: S, right: S) -> Self {
+        self.suffixes = (left.into(), right.into());
+        self
+    }
+}
+
+/// A left row, a right row or both, by position in their table.
+type Pair = (Option<usize>, Option<usize>);
+
+/// Where the key columns sit on either side and which right columns make it
+/// into the result.
+struct Layout {
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+    right_values: Vec<usize>,
+    left_width: usize,
+}
+
+impl Layout {
+    fn row(
+        &self,
+        left: Option<&[String]>,
+        right: Option<&[String]>,
+        with_right: bool,
+    ) -> Vec<String> {
+        let mut row = match (left, right) {
+            (Some(left), _) => left.to_vec(),
+            (None, Some(right)) => {
+                let mut row = vec![String::new(); self.left_width];
+                for (&l, &r) in self.left_keys.iter().zip(&self.right_keys) {
+                    row[l] = right[r].clone();
+                }
+                row
+            }
+            (None, None) => unreachable!("a join pair always has a side"),
+        };
+        if with_right {
+            row.extend(
+                self.right_values
+                    .iter()
+                    .map(|&i| right.map(|right| right[i].clone()).unwrap_or_default()),
+            );
+        }
+        row
+    }
+}
+
+fn key_of(row: &[String], keys: &[usize]) -> Vec<String> {
+    keys.iter().map(|&i| row[i].clone()).collect()
+}
+
+/// Fails with [`CsvError::Unsorted`] on the first row whose key sorts
+/// before the key of the row above it.
+pub(crate) fn check_sorted(csv: &CsvData, keys: &[usize]) -> Result<(), CsvError> {
+    let offset = if csv.headers.is_some() { 2 } else { 1 };
+    let rows: Vec<&[String]> = csv.rows().collect();
+    for (i, pair) in rows.windows(2).enumerate() {
+        if key_of(pair[1], keys) < key_of(pair[0], keys) {
+            return Err(CsvError::Unsorted {
+                line: i + 1 + offset,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl CsvData {
+    /// Joins `self` with `right` on `keys`, which are looked up in each
+    /// table's own header. Header names shared by a left and a right column
+    /// get the suffixes from `options`.
+    pub fn join(
+        &self,
+        right: &CsvData,
+        keys: &[Column],
+        options: &JoinOptions,
+    ) -> Result<CsvData, CsvError> {
+        check_dialects(self, right)?;
+        let left_keys = self.column_indexes(keys)?;
+        let right_keys = right.column_indexes(keys)?;
+        let layout = Layout {
+            right_values: (0..right.line_width)
+                .filter(|i| !right_keys.contains(i))
+                .collect(),
+            left_keys,
+            right_keys,
+            left_width: self.line_width,
+        };
+
+        let left_rows: Vec<&[String]> = self.rows().collect();
+        let right_rows: Vec<&[String]> = right.rows().collect();
+        let pairs = match options.algorithm {
+            JoinAlgorithm::Hash => hash_pairs(&left_rows, &right_rows, &layout),
+            JoinAlgorithm::SortMerge => {
+                check_sorted(self, &layout.left_keys)?;
+                check_sorted(right, &layout.right_keys)?;
+                merge_pairs(&left_rows, &right_rows, &layout)
+            }
+        };
+
+        let with_right = !matches!(options.kind, JoinKind::Semi | JoinKind::Anti);
+        let mut last_semi = None;
+        let mut data = Vec::new();
+        for (l, r) in pairs {
+            let keep = match options.kind {
+                JoinKind::Inner => l.is_some() && r.is_some(),
+                JoinKind::Left => l.is_some(),
+                JoinKind::Right => r.is_some(),
+                JoinKind::Full => true,
+                JoinKind::Semi => l.is_some() && r.is_some() && last_semi.replace(l) != Some(l),
+                JoinKind::Anti => r.is_none(),
+            };
+            if keep {
+                let left = l.map(|l| left_rows[l]);
+                let right = r.map(|r| right_rows[r]);
+                data.extend(layout.row(left, right, with_right));
+            }
+        }
+
+        let line_width = match with_right {
+            true => layout.left_width + layout.right_values.len(),
+            false => layout.left_width,
+        };
+        Ok(CsvData {
+            data,
+            dialect: self.dialect,
+            line_width,
+            headers: self.join_headers(right, &layout, with_right, options),
+        })
+    }
+
+    fn join_headers(
+        &self,
+        right: &CsvData,
+        layout: &Layout,
+        with_right: bool,
+        options: &JoinOptions,
+    ) -> Option<Vec<String>> {
+        if self.headers.is_none() && (right.headers.is_none() || !with_right) {
+            return None;
+        }
+        let name = |csv: &CsvData, i: usize| -> String {
+            csv.headers
+                .as_ref()
+                .map(|headers| headers[i].clone())
+                .unwrap_or_default()
+        };
+        let mut left: Vec<String> = (0..layout.left_width).map(|i| name(self, i)).collect();
+        if !with_right {
+            return Some(left);
+        }
+
+        let mut right: Vec<String> = layout
+            .right_values
+            .iter()
+            .map(|&i| name(right, i))
+            .collect();
+        let (left_suffix, right_suffix) = &options.suffixes;
+        let clashes: Vec<String> = right
+            .iter()
+            .filter(|name| !name.is_empty() && left.contains(name))
+            .cloned()
+            .collect();
+        for name in left.iter_mut().filter(|name| clashes.contains(name)) {
+            name.push_str(left_suffix);
+        }
+        for name in right.iter_mut().filter(|name| clashes.contains(name)) {
+            name.push_str(right_suffix);
+        }
+        left.extend(right);
+        Some(left)
+    }
+}
+
+/// Every pair of a full outer join, in left order with the unmatched right
+/// rows at the end.
+fn hash_pairs(left: &[&[String]], right: &[&[String]], layout: &Layout) -> Vec<Pair> {
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (i, row) in right.iter().enumerate() {
+        index
+            .entry(key_of(row, &layout.right_keys))
+            .or_default()
+            .push(i);
+    }
+
+    let mut matched = vec![false; right.len()];
+    let mut pairs = Vec::new();
+    for (i, row) in left.iter().enumerate() {
+        match index.get(&key_of(row, &layout.left_keys)) {
+            Some(matches) => {
+                for &j in matches {
+                    matched[j] = true;
+                    pairs.push((Some(i), Some(j)));
+                }
+            }
+            None => pairs.push((Some(i), None)),
+        }
+    }
+    pairs.extend(
+        (0..right.len())
+            .filter(|&j| !matched[j])
+            .map(|j| (None, Some(j))),
+    );
+    pairs
+}
+
+/// Every pair of a full outer join of two tables sorted by key, in key
+/// order.
+fn merge_pairs(left: &[&[String]], right: &[&[String]], layout: &Layout) -> Vec<Pair> {
+    let left_key = |i: usize| key_of(left[i], &layout.left_keys);
+    let right_key = |j: usize| key_of(right[j], &layout.right_keys);
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        let key = left_key(i);
+        match key.cmp(&right_key(j)) {
+            Ordering::Less => {
+                pairs.push((Some(i), None));
+                i += 1;
+            }
+            Ordering::Greater => {
+                pairs.push((None, Some(j)));
+                j += 1;
+            }
+            Ordering::Equal => {
+                let left_end = (i..left.len())
+                    .find(|&k| left_key(k) != key)
+                    .unwrap_or(left.len());
+                let right_end = (j..right.len())
+                    .find(|&k| right_key(k) != key)
+                    .unwrap_or(right.len());
+                for l in i..left_end {
+                    pairs.extend((j..right_end).map(|r| (Some(l), Some(r))));
+                }
+                i = left_end;
+                j = right_end;
+            }
+        }
+    }
+    pairs.extend((i..left.len()).map(|l| (Some(l), None)));
+    pairs.extend((j..right.len()).map(|r| (None, Some(r))));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::Column;
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+    use crate::join::{JoinAlgorithm, JoinKind, JoinOptions};
+
+    fn people() -> CsvData {
+        CsvData::from_text_with_headers("id,name\n1,ann\n2,bob\n3,cy".to_string(), ',').unwrap()
+    }
+
+    fn orders() -> CsvData {
+        CsvData::from_text_with_headers(
+            "name,id,item\nann,1,pen\nann,1,ink\nzed,4,cup".to_string(),
+            ',',
+        )
+        .unwrap()
+    }
+
+    fn join(kind: JoinKind, algorithm: JoinAlgorithm) -> CsvData {
+        let options = JoinOptions::default().kind(kind).algorithm(algorithm);
+        people()
+            .join(&orders(), &[Column::from("id")], &options)
+            .unwrap()
+    }
+
+    fn rows(csv: CsvData) -> Vec<String> {
+        csv.into_iter().map(|row| row.join(",")).collect()
+    }
+
+    #[test]
+    fn test_join_kinds() {
+        for algorithm in [JoinAlgorithm::Hash, JoinAlgorithm::SortMerge] {
+            let inner = join(JoinKind::Inner, algorithm);
+            assert_eq!(
+                inner.headers().unwrap(),
+                ["id", "name_left", "name_right", "item"]
+            );
+            assert_eq!(rows(inner), ["1,ann,ann,pen", "1,ann,ann,ink"]);
+            assert_eq!(
+                rows(join(JoinKind::Left, algorithm)),
+                ["1,ann,ann,pen", "1,ann,ann,ink", "2,bob,,", "3,cy,,"]
+            );
+            assert_eq!(
+                rows(join(JoinKind::Right, algorithm)),
+                ["1,ann,ann,pen", "1,ann,ann,ink", "4,,zed,cup"]
+            );
+            assert_eq!(
+                rows(join(JoinKind::Full, algorithm)),
+                [
+                    "1,ann,ann,pen",
+                    "1,ann,ann,ink",
+                    "2,bob,,",
+                    "3,cy,,",
+                    "4,,zed,cup"
+                ]
+            );
+
+            let semi = join(JoinKind::Semi, algorithm);
+            assert_eq!(semi.headers().unwrap(), ["id", "name"]);
+            assert_eq!(rows(semi), ["1,ann"]);
+            assert_eq!(rows(join(JoinKind::Anti, algorithm)), ["2,bob", "3,cy"]);
+        }
+    }
+
+    #[test]
+    fn test_join_multiple_keys_without_headers() {
+        let left = CsvData::from_text("a,1,x\na,2,y\nb,1,z".to_string(), ',').unwrap();
+        let right = CsvData::from_text("b,1,q\na,1,p".to_string(), ',').unwrap();
+        let options = JoinOptions::default().kind(JoinKind::Left);
+        let result = left
+            .join(&right, &[Column::Index(0), Column::Index(1)], &options)
+            .unwrap();
+        assert_eq!(result.headers(), None);
+        assert_eq!(rows(result), ["a,1,x,p", "a,2,y,", "b,1,z,q"]);
+    }
+
+    #[test]
+    fn test_sort_merge_requires_sorted_input() {
+        let options = JoinOptions::default().algorithm(JoinAlgorithm::SortMerge);
+        let unsorted = CsvData::from_text_with_headers("id\n1\n3\n2".to_string(), ',').unwrap();
+        let result = people().join(&unsorted, &[Column::from("id")], &options);
+        assert!(matches!(result, Err(CsvError::Unsorted { line: 4 })));
+
+        let options = options.suffixes("_a", "_b");
+        let result = people()
+            .join(&people(), &[Column::from("id")], &options)
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["id", "name_a", "name_b"]);
+    }
+}
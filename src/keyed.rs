@@ -0,0 +1,351 @@
+//! Set operations that decide membership on a subset of the columns.
+//!
+//! Rows count as equal when their key columns are equal, whatever the other
+//! columns hold. Key names are looked up in each table's own header, so the
+//! key may sit at a different position on either side. When both tables have
+//! a header row, the rows of one are moved into the column order of the other
+//! by name, so every row of the result lines up with its header.
+
+use std::borrow::Cow;
+use std::cmp::max;
+use std::collections::HashMap;
+
+use crate::column::{Column, ConflictPolicy};
+use crate::csvdata::{check_dialects, CsvData};
+use crate::error::CsvError;
+
+/// Rows of one table grouped by their key, in the order they appear.
+pub(crate) struct KeyedRows<'a> {
+    pub(crate) rows: Vec<(Vec<String>, &'a [String])>,
+    pub(crate) groups: HashMap<Vec<String>, Vec<&'a [String]>>,
+}
+
+impl<'a> KeyedRows<'a> {
+    pub(crate) fn new(csv: &'a CsvData, key_columns: &[usize]) -> Self {
+        let mut rows = Vec::new();
+        let mut groups: HashMap<Vec<String>, Vec<&[String]>> = HashMap::new();
+        for row in csv.rows() {
+            let key: Vec<String> = key_columns.iter().map(|&i| row[i].clone()).collect();
+            groups.entry(key.clone()).or_default().push(row);
+            rows.push((key, row));
+        }
+        KeyedRows { rows, groups }
+    }
+
+    fn contains(&self, key: &[String]) -> bool {
+        self.groups.contains_key(key)
+    }
+
+    fn all(&self) -> Vec<&'a [String]> {
+        self.rows.iter().map(|(_, row)| *row).collect()
+    }
+
+    /// Rows whose key is (`present`) or is not (`!present`) in `other`.
+    fn rows_where(&self, other: &KeyedRows, present: bool) -> Vec<&'a [String]> {
+        self.rows
+            .iter()
+            .filter(|(key, _)| other.contains(key) == present)
+            .map(|(_, row)| *row)
+            .collect()
+    }
+
+    /// Fails on the first key shared with `other` whose rows differ.
+    fn check_conflicts(&self, other: &KeyedRows) -> Result<(), CsvError> {
+        for (key, _) in &self.rows {
+            if let Some(other_rows) = other.groups.get(key) {
+                let mut left = self.groups[key].clone();
+                let mut right = other_rows.clone();
+                left.sort();
+                right.sort();
+                if left != right {
+                    return Err(CsvError::KeyConflict { key: key.clone() });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CsvData {
+    /// Union that matches rows on `keys` only. Rows whose key is only on one
+    /// side are kept; for keys on both sides `policy` picks the side. Rows
+    /// from `self` come first, then the ones from `second`.
+    pub fn union_by_key(
+        &self,
+        second: &CsvData,
+        keys: &[Column],
+        policy: ConflictPolicy,
+    ) -> Result<CsvData, CsvError> {
+        let right_headers = policy == ConflictPolicy::KeepRight;
+        self.keyed(second, keys, right_headers, |left, right| {
+            let mut rows = match policy {
+                ConflictPolicy::KeepLeft => left.all(),
+                ConflictPolicy::KeepRight => left.rows_where(right, false),
+                ConflictPolicy::Error => {
+                    left.check_conflicts(right)?;
+                    left.all()
+                }
+            };
+            rows.extend(match policy {
+                ConflictPolicy::KeepRight => right.all(),
+                _ => right.rows_where(left, false),
+            });
+            Ok(rows)
+        })
+    }
+
+    /// Rows whose key occurs in both tables, taken from the side `policy`
+    /// picks.
+    pub fn intersection_by_key(
+        &self,
+        second: &CsvData,
+        keys: &[Column],
+        policy: ConflictPolicy,
+    ) -> Result<CsvData, CsvError> {
+        let right_headers = policy == ConflictPolicy::KeepRight;
+        self.keyed(second, keys, right_headers, |left, right| match policy {
+            ConflictPolicy::KeepLeft => Ok(left.rows_where(right, true)),
+            ConflictPolicy::KeepRight => Ok(right.rows_where(left, true)),
+            ConflictPolicy::Error => {
+                left.check_conflicts(right)?;
+                Ok(left.rows_where(right, true))
+            }
+        })
+    }
+
+    /// Rows of `self` whose key does not occur in `second`.
+    pub fn except_by_key(&self, second: &CsvData, keys: &[Column]) -> Result<CsvData, CsvError> {
+        self.keyed(second, keys, false, |left, right| {
+            Ok(left.rows_where(right, false))
+        })
+    }
+
+    /// Rows whose key occurs in only one of the tables, the ones from `self`
+    /// first.
+    pub fn difference_by_key(
+        &self,
+        second: &CsvData,
+        keys: &[Column],
+    ) -> Result<CsvData, CsvError> {
+        self.keyed(second, keys, false, |left, right| {
+            let mut rows = left.rows_where(right, false);
+            rows.extend(right.rows_where(left, false));
+            Ok(rows)
+        })
+    }
+
+    /// Runs `f` on the rows of both tables grouped by key. The result is in
+    /// the column order of `second` if `right_headers` is set, else in the
+    /// one of `self`.
+    fn keyed<F>(
+        &self,
+        second: &CsvData,
+        keys: &[Column],
+        right_headers: bool,
+        f: F,
+    ) -> Result<CsvData, CsvError>
+    where
+        F: for<'a> FnOnce(&KeyedRows<'a>, &KeyedRows<'a>) -> Result<Vec<&'a [String]>, CsvError>,
+    {
+        check_dialects(self, second)?;
+        let mut left_columns = self.column_indexes(keys)?;
+        let mut right_columns = second.column_indexes(keys)?;
+
+        let (mut first, mut second) = (Cow::Borrowed(self), Cow::Borrowed(second));
+        if let (Some(left), Some(right)) = (&first.headers, &second.headers) {
+            if left != right {
+                let (moved, headers, columns) = match right_headers {
+                    true => (&mut first, right.clone(), &mut left_columns),
+                    false => (&mut second, left.clone(), &mut right_columns),
+                };
+                let order = alignment(moved, &headers)?;
+                for column in columns.iter_mut() {
+                    *column = order.iter().position(|i| i == column).unwrap_or(*column);
+                }
+                *moved = Cow::Owned(moved.project(&order));
+            }
+        }
+
+        let width = max(first.line_width, second.line_width);
+        let left = KeyedRows::new(&first, &left_columns);
+        let right = KeyedRows::new(&second, &right_columns);
+
+        let mut data = Vec::new();
+        for row in f(&left, &right)? {
+            data.extend(row.iter().cloned());
+            data.extend((row.len()..width).map(|_| "".to_string()));
+        }
+
+        Ok(CsvData {
+            data,
+            dialect: self.dialect,
+            line_width: width,
+            headers: match right_headers {
+                true => second.merged_headers(&first, width)?,
+                false => first.merged_headers(&second, width)?,
+            },
+        })
+    }
+}
+
+/// For every one of `headers`, the position of the column of `csv` with that
+/// name. Fails unless `csv` has exactly these columns, in any order.
+fn alignment(csv: &CsvData, headers: &[String]) -> Result<Vec<usize>, CsvError> {
+    let own = csv.headers.as_deref().unwrap_or_default();
+    let mismatch = || CsvError::HeaderMismatch {
+        left: headers.to_vec(),
+        right: own.to_vec(),
+    };
+    let mut used = vec![false; own.len()];
+    let mut order = Vec::new();
+    for name in headers {
+        let i = (0..own.len())
+            .find(|&i| !used[i] && own[i] == *name)
+            .ok_or_else(mismatch)?;
+        used[i] = true;
+        order.push(i);
+    }
+    match order.len() == own.len() {
+        true => Ok(order),
+        false => Err(mismatch()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::{Column, ConflictPolicy};
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+
+    fn left() -> CsvData {
+        CsvData::from_text_with_headers(
+            "id,name,updated_at\n1,a,mon\n2,b,mon\n3,c,mon".to_string(),
+            ',',
+        )
+        .unwrap()
+    }
+
+    fn right() -> CsvData {
+        CsvData::from_text_with_headers("updated_at,id,name\ntue,2,b\ntue,4,d".to_string(), ',')
+            .unwrap()
+    }
+
+    fn key() -> Vec<Column> {
+        vec![Column::from("id")]
+    }
+
+    #[test]
+    fn test_union_by_key() {
+        let result = left()
+            .union_by_key(&left(), &key(), ConflictPolicy::KeepLeft)
+            .unwrap();
+        assert_eq!(result, left());
+
+        let tmp = CsvData::from_text("1,a,x\n2,b,x".to_string(), ',').unwrap();
+        let tmp2 = CsvData::from_text("2,b,y\n3,c,y".to_string(), ',').unwrap();
+        let keys = [Column::Index(0), Column::Index(1)];
+
+        let result = tmp
+            .union_by_key(&tmp2, &keys, ConflictPolicy::KeepLeft)
+            .unwrap();
+        assert_eq!(
+            result.data,
+            vec!["1", "a", "x", "2", "b", "x", "3", "c", "y"]
+        );
+        let result = tmp
+            .union_by_key(&tmp2, &keys, ConflictPolicy::KeepRight)
+            .unwrap();
+        assert_eq!(
+            result.data,
+            vec!["1", "a", "x", "2", "b", "y", "3", "c", "y"]
+        );
+    }
+
+    #[test]
+    fn test_key_names_resolved_per_table() {
+        let result = left()
+            .intersection_by_key(&right(), &key(), ConflictPolicy::KeepLeft)
+            .unwrap();
+        assert_eq!(result.data, vec!["2", "b", "mon"]);
+        assert_eq!(result.column_index("updated_at"), Some(2));
+
+        let result = left()
+            .intersection_by_key(&right(), &key(), ConflictPolicy::KeepRight)
+            .unwrap();
+        assert_eq!(result.data, vec!["tue", "2", "b"]);
+        assert_eq!(result.column_index("updated_at"), Some(0));
+    }
+
+    #[test]
+    fn test_difference_by_key() {
+        let result = left().difference_by_key(&right(), &key()).unwrap();
+        assert_eq!(result.headers().unwrap(), ["id", "name", "updated_at"]);
+        assert_eq!(
+            result.data,
+            vec!["1", "a", "mon", "3", "c", "mon", "4", "d", "tue"]
+        );
+
+        let result = left()
+            .union_by_key(&right(), &key(), ConflictPolicy::KeepRight)
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["updated_at", "id", "name"]);
+        assert_eq!(
+            result.data,
+            vec!["mon", "1", "a", "mon", "3", "c", "tue", "2", "b", "tue", "4", "d"]
+        );
+
+        let other = CsvData::from_text_with_headers("id,name\n5,e".to_string(), ',').unwrap();
+        assert!(matches!(
+            left().difference_by_key(&other, &key()),
+            Err(CsvError::HeaderMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_except_by_key() {
+        let result = left().except_by_key(&right(), &key()).unwrap();
+        assert_eq!(result.data, vec!["1", "a", "mon", "3", "c", "mon"]);
+
+        // Same columns in another order, compared on a two-column key.
+        let reordered = CsvData::from_text_with_headers(
+            "name,updated_at,id\nb,tue,2\nz,tue,3".to_string(),
+            ',',
+        )
+        .unwrap();
+        let keys = [Column::from("id"), Column::from("name")];
+        let result = left().except_by_key(&reordered, &keys).unwrap();
+        assert_eq!(result.headers().unwrap(), ["id", "name", "updated_at"]);
+        assert_eq!(result.data, vec!["1", "a", "mon", "3", "c", "mon"]);
+        let result = reordered.except_by_key(&left(), &keys).unwrap();
+        assert_eq!(result.headers().unwrap(), ["name", "updated_at", "id"]);
+        assert_eq!(result.data, vec!["z", "tue", "3"]);
+    }
+
+    #[test]
+    fn test_conflict_policy_error() {
+        let tmp = CsvData::from_text("1,a\n2,b".to_string(), ',').unwrap();
+        let same = CsvData::from_text("2,b".to_string(), ',').unwrap();
+        let changed = CsvData::from_text("2,z".to_string(), ',').unwrap();
+        let keys = [Column::Index(0)];
+
+        let result = tmp
+            .intersection_by_key(&same, &keys, ConflictPolicy::Error)
+            .unwrap();
+        assert_eq!(result.data, vec!["2", "b"]);
+
+        let result = tmp.union_by_key(&changed, &keys, ConflictPolicy::Error);
+        assert!(matches!(
+            result,
+            Err(CsvError::KeyConflict { key }) if key == vec!["2"]
+        ));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let result = left().difference_by_key(&right(), &[Column::from("missing")]);
+        assert!(matches!(result, Err(CsvError::UnknownColumn { .. })));
+
+        let result = left().difference_by_key(&right(), &[Column::Index(3)]);
+        assert!(matches!(result, Err(CsvError::UnknownColumn { .. })));
+    }
+}
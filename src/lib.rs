@@ -1 +1,63 @@
+//! Set operations and transformations on CSV tables.
+//!
+//! [`CsvData`] holds a whole table in memory and offers union, intersection,
+//! difference, transpose, grouping and reshaping; [`CsvReader`] and
+//! [`CsvWriter`] stream records for inputs that do not fit in memory.
+//! `use rcsvtools::prelude::*;` brings the commonly used items into scope.
+
+mod column;
 mod csvdata;
+mod dialect;
+mod diff;
+mod encoding;
+mod error;
+mod external;
+mod filter;
+mod groupby;
+mod join;
+mod keyed;
+mod projection;
+mod reader;
+mod regex;
+mod reshape;
+mod setops;
+mod sort;
+mod streaming;
+mod tokenizer;
+mod writer;
+
+pub use crate::column::{Column, ConflictPolicy};
+pub use crate::csvdata::{
+    difference_all, difference_all_with, intersection_all, intersection_all_with, pad, union_all,
+    union_all_with, CsvData, CsvDataIntoIterator, CsvDataIterator,
+};
+pub use crate::dialect::{
+    sniff, sniff_reader, sniff_reader_with_encoding, Dialect, LineTerminator, Trim,
+};
+pub use crate::diff::{CellChange, DiffReport, ModifiedRow};
+pub use crate::encoding::{detect_encoding, Encoding};
+pub use crate::error::CsvError;
+pub use crate::external::{ExternalSort, SortedRows};
+pub use crate::filter::Predicate;
+pub use crate::groupby::{Aggregate, Aggregation, GroupBy};
+pub use crate::join::{JoinAlgorithm, JoinKind, JoinOptions};
+pub use crate::reader::{CsvReader, ReaderBuilder};
+pub use crate::setops::{RowOrder, Semantics, SetOptions};
+pub use crate::sort::{Comparison, NullOrder, SortKey, SortOrder};
+pub use crate::streaming::{
+    difference_all_sorted, difference_all_sorted_with, intersection_all_sorted,
+    intersection_all_sorted_with, union_all_sorted, union_all_sorted_with, SortedMerge,
+};
+pub use crate::writer::{CsvWriter, QuoteStyle, WriterBuilder};
+
+pub mod prelude {
+    pub use crate::{
+        difference_all, difference_all_sorted, difference_all_with, intersection_all,
+        intersection_all_sorted, intersection_all_with, sniff, sniff_reader,
+        sniff_reader_with_encoding, union_all, union_all_sorted, union_all_with, Aggregate,
+        Aggregation, Column, Comparison, ConflictPolicy, CsvData, CsvError, CsvReader, CsvWriter,
+        Dialect, DiffReport, Encoding, ExternalSort, JoinAlgorithm, JoinKind, JoinOptions,
+        LineTerminator, NullOrder, Predicate, QuoteStyle, ReaderBuilder, RowOrder, Semantics,
+        SetOptions, SortKey, SortOrder, WriterBuilder,
+    };
+}
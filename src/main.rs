@@ -0,0 +1,1086 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::ExitCode;
+
+use rcsvtools::prelude::*;
+
+const USAGE: &str = "\
+usage: rcsvtools <command> [options] [FILE...]
+       rcsvtools filter [options] <EXPR> [FILE...]
+
+commands:
+    union        union of all inputs, keeping duplicates
+    intersect    rows present in every input
+    diff         rows present in exactly one input
+    except       rows of the first input not in any other, keeping surplus
+                 duplicates (alias `minus`)
+    join         join two inputs on the --key columns
+    transpose    swap rows and columns of a single input
+    cat          concatenate all inputs in order
+    sort         rows of all inputs sorted on the --by keys
+    filter       rows of all inputs for which EXPR holds, e.g.
+                 `status == 'active' and amount > 100`
+    groupby      one row per distinct --key of all inputs, with the --agg
+                 columns aggregated over its rows
+
+options:
+    -d, --delimiter <CHAR>  field delimiter, `\\t` or `tab` for tabs (default: detected
+                            from each input, and the first input's on output)
+    -o, --output <FILE>     write to FILE instead of stdout
+        --terminator <EOL>  end output lines with `lf`, `crlf` or `cr` (default: the
+                            first input's line endings)
+        --encoding <ENC>    read every input in ENC (default: detected from each input)
+        --output-encoding <ENC>
+                            write in ENC (default: `utf-8`)
+        --bom               start the output with a byte order mark, as Excel expects
+    -H, --headers           treat the first line of every input as a header
+        --order <ORDER>     `sorted` (default) or `input` to keep first-seen row order;
+                            `cat` always keeps input order
+        --distinct          output every row at most once, like SQL UNION / INTERSECT
+    -k, --key <COLUMNS>     compare rows on these columns only
+        --on-conflict <P>   with --key, rows on both sides come from `left` (default),
+                            `right`, or fail with `error` unless they are identical
+        --report <FORMAT>   with diff --key, report added, removed and modified rows
+                            as a `summary` or as `csv` with one line per changed cell
+        --how <KIND>        join kind: `inner` (default), `left`, `right`, `full`,
+                            `semi` or `anti`
+        --sorted            join inputs already sorted by key with a sort-merge
+                            instead of a hash join
+        --by <KEYS>         sort keys, each a column optionally followed by `:asc`
+                            or `:desc`, `:str`, `:num`, `:date` or `:natural`, and
+                            `:nulls-first` or `:nulls-last`, e.g. `amount:num:desc`;
+                            a column name with a `:` needs a flag after it, and
+                            a comma in it is written `\\,`
+        --memory <SIZE>     sort larger-than-memory inputs as a stream, spilling
+                            sorted runs to temporary files past SIZE bytes
+                            (suffixes `K`, `M` and `G`)
+        --agg <AGGS>        aggregations, each a column followed by `:count`, `:sum`,
+                            `:min`, `:max`, `:mean`, `:median`, `:first`, `:last`,
+                            `:count-distinct` or `:concat`, optionally with a
+                            separator as in `:concat=|` (default `;`); write a
+                            comma in a separator or column name as `\\,`
+        --select <COLUMNS>  keep only these columns of every input, in this order
+        --drop <COLUMNS>    remove these columns from every input
+        --reorder <COLUMNS> move these columns of every input to the front
+    -h, --help              print this help
+
+FILE may be `-` for stdin, which is also used when no FILE is given.
+ENC is `utf-8`, `utf-16le`, `utf-16be`, `latin-1` or `windows-1252`.
+COLUMNS is a comma-separated list of 0-based indexes, inclusive index ranges
+like `2-4` or `3-`, and header names. --select, --drop and --reorder apply
+in the order given, before the command runs.";
+
+/// Bytes of every input looked at to detect its delimiter.
+const SNIFF_SAMPLE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Command {
+    Union,
+    Intersect,
+    Diff,
+    Except,
+    Join,
+    Transpose,
+    Cat,
+    Filter,
+    Sort,
+    GroupBy,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Projection {
+    Select(Vec<Column>),
+    Drop(Vec<Column>),
+    Reorder(Vec<Column>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Report {
+    Summary,
+    Csv,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Options {
+    command: Command,
+    delimiter: Option<char>,
+    terminator: Option<LineTerminator>,
+    encoding: Option<Encoding>,
+    output_encoding: Encoding,
+    bom: bool,
+    output: Option<String>,
+    has_headers: bool,
+    set_options: SetOptions,
+    keys: Vec<Column>,
+    conflict_policy: ConflictPolicy,
+    report: Option<Report>,
+    join_options: JoinOptions,
+    projections: Vec<Projection>,
+    expression: Option<String>,
+    sort_keys: Vec<SortKey>,
+    aggregations: Vec<Aggregation>,
+    memory: Option<usize>,
+    inputs: Vec<String>,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Help,
+    Csv(CsvError),
+    Input(String, CsvError),
+    Expression(String, CsvError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Csv(e) => write!(f, "{}", e),
+            CliError::Input(input, e) => write!(f, "{}: {}", input, e),
+            CliError::Expression(expression, e) => match e {
+                CsvError::Expression { position, .. } => write!(
+                    f,
+                    "{}\n    {}\n    {:>width$}",
+                    e,
+                    expression,
+                    "^",
+                    width = position
+                ),
+                _ => write!(f, "{}", e),
+            },
+        }
+    }
+}
+
+impl From<CsvError> for CliError {
+    fn from(e: CsvError) -> Self {
+        CliError::Csv(e)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match parse_args(&args).and_then(|options| run(&options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Help) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Err(CliError::Usage(message)) => {
+            eprintln!("rcsvtools: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            eprintln!("rcsvtools: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("union") => Command::Union,
+        Some("intersect") => Command::Intersect,
+        Some("diff") => Command::Diff,
+        Some("except") | Some("minus") => Command::Except,
+        Some("join") => Command::Join,
+        Some("transpose") => Command::Transpose,
+        Some("cat") => Command::Cat,
+        Some("filter") => Command::Filter,
+        Some("sort") => Command::Sort,
+        Some("groupby") => Command::GroupBy,
+        Some("-h") | Some("--help") => return Err(CliError::Help),
+        Some(other) => return Err(CliError::Usage(format!("unknown command `{}`", other))),
+        None => return Err(CliError::Usage("missing command".to_string())),
+    };
+
+    let mut options = Options {
+        command,
+        delimiter: None,
+        terminator: None,
+        encoding: None,
+        output_encoding: Encoding::default(),
+        bom: false,
+        output: None,
+        has_headers: false,
+        set_options: SetOptions::default(),
+        keys: Vec::new(),
+        conflict_policy: ConflictPolicy::default(),
+        report: None,
+        join_options: JoinOptions::default(),
+        projections: Vec::new(),
+        expression: None,
+        sort_keys: Vec::new(),
+        aggregations: Vec::new(),
+        memory: None,
+        inputs: Vec::new(),
+    };
+    let mut order_given = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--delimiter" => {
+                options.delimiter = Some(parse_delimiter(value(arg, args.next())?)?)
+            }
+            "--terminator" => {
+                options.terminator = Some(parse_terminator(value(arg, args.next())?)?)
+            }
+            "--encoding" => options.encoding = Some(parse_encoding(value(arg, args.next())?)?),
+            "--output-encoding" => {
+                options.output_encoding = parse_encoding(value(arg, args.next())?)?
+            }
+            "--bom" => options.bom = true,
+            "-o" | "--output" => options.output = Some(value(arg, args.next())?.to_string()),
+            "-H" | "--headers" => options.has_headers = true,
+            "-k" | "--key" => options.keys = parse_columns(value(arg, args.next())?)?,
+            "--on-conflict" => {
+                options.conflict_policy = parse_conflict_policy(value(arg, args.next())?)?
+            }
+            "--report" => options.report = Some(parse_report(value(arg, args.next())?)?),
+            "--select" => {
+                let columns = parse_columns(value(arg, args.next())?)?;
+                options.projections.push(Projection::Select(columns));
+            }
+            "--drop" => {
+                let columns = parse_columns(value(arg, args.next())?)?;
+                options.projections.push(Projection::Drop(columns));
+            }
+            "--reorder" => {
+                let columns = parse_columns(value(arg, args.next())?)?;
+                options.projections.push(Projection::Reorder(columns));
+            }
+            "--by" => options.sort_keys = parse_sort_keys(value(arg, args.next())?)?,
+            "--agg" => options.aggregations = parse_aggregations(value(arg, args.next())?)?,
+            "--memory" => options.memory = Some(parse_size(value(arg, args.next())?)?),
+            "--how" => {
+                let kind = parse_join_kind(value(arg, args.next())?)?;
+                options.join_options = options.join_options.kind(kind);
+            }
+            "--sorted" => {
+                options.join_options = options.join_options.algorithm(JoinAlgorithm::SortMerge);
+            }
+            "--distinct" => {
+                options.set_options = options.set_options.semantics(Semantics::Distinct);
+            }
+            "--order" => {
+                let order = parse_order(value(arg, args.next())?)?;
+                options.set_options = options.set_options.order(order);
+                order_given = true;
+            }
+            "-h" | "--help" => return Err(CliError::Help),
+            "-" => options.inputs.push(arg.clone()),
+            flag if flag.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option `{}`", flag)))
+            }
+            _ => options.inputs.push(arg.clone()),
+        }
+    }
+
+    if options.command == Command::Filter {
+        if options.inputs.is_empty() {
+            return Err(CliError::Usage("filter needs an expression".to_string()));
+        }
+        options.expression = Some(options.inputs.remove(0));
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push("-".to_string());
+    }
+    if options.inputs.iter().filter(|input| *input == "-").count() > 1 {
+        return Err(CliError::Usage("stdin can only be read once".to_string()));
+    }
+    if order_given && options.command == Command::Cat {
+        return Err(CliError::Usage(
+            "--order does not apply to cat, which keeps input order".to_string(),
+        ));
+    }
+    if options.command == Command::Transpose && options.inputs.len() != 1 {
+        return Err(CliError::Usage(
+            "transpose takes exactly one input".to_string(),
+        ));
+    }
+    if !options.keys.is_empty() && options.command == Command::Diff && options.inputs.len() != 2 {
+        return Err(CliError::Usage(
+            "diff with --key takes exactly two inputs".to_string(),
+        ));
+    }
+    if options.command == Command::Sort && options.sort_keys.is_empty() {
+        return Err(CliError::Usage("sort needs --by".to_string()));
+    }
+    if options.command == Command::GroupBy && options.aggregations.is_empty() {
+        return Err(CliError::Usage("groupby needs --agg".to_string()));
+    }
+    if options.memory.is_some()
+        && (options.command != Command::Sort || !options.projections.is_empty())
+    {
+        return Err(CliError::Usage(
+            "--memory only applies to sort without --select, --drop or --reorder".to_string(),
+        ));
+    }
+    if options.command == Command::Join && (options.keys.is_empty() || options.inputs.len() != 2) {
+        return Err(CliError::Usage(
+            "join takes --key and exactly two inputs".to_string(),
+        ));
+    }
+    if options.report.is_some() && (options.command != Command::Diff || options.keys.is_empty()) {
+        return Err(CliError::Usage(
+            "--report only applies to diff with --key".to_string(),
+        ));
+    }
+    Ok(options)
+}
+
+fn parse_columns(value: &str) -> Result<Vec<Column>, CliError> {
+    value.split(',').map(parse_column).collect()
+}
+
+fn parse_column(column: &str) -> Result<Column, CliError> {
+    if let Ok(index) = column.parse::<usize>() {
+        return Ok(Column::Index(index));
+    }
+    if let Some((start, end)) = column.split_once('-') {
+        match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start > end => {
+                return Err(CliError::Usage(format!(
+                    "column range `{}` ends before it starts",
+                    column
+                )))
+            }
+            (Ok(start), Ok(end)) => return Ok(Column::from(start..=end)),
+            (Ok(start), Err(_)) if end.is_empty() => return Ok(Column::from(start..)),
+            _ => {}
+        }
+    }
+    Ok(Column::from(column))
+}
+
+fn parse_sort_keys(value: &str) -> Result<Vec<SortKey>, CliError> {
+    split_escaped(value)
+        .iter()
+        .map(|spec| {
+            // Flags are taken from the end, so that header names may contain
+            // colons as long as a flag follows them.
+            let mut column = spec.as_str();
+            let mut flags = Vec::new();
+            while let Some((rest, flag)) = column.rsplit_once(':') {
+                match sort_flag(flag) {
+                    Some(apply) => flags.push(apply),
+                    None if flags.is_empty() => {
+                        return Err(CliError::Usage(format!(
+                            "unknown sort flag `{}` in `{}`",
+                            flag, spec
+                        )))
+                    }
+                    None => break,
+                }
+                column = rest;
+            }
+            let key = SortKey::new(parse_column(column)?);
+            Ok(flags.into_iter().rev().fold(key, |key, apply| apply(key)))
+        })
+        .collect()
+}
+
+/// What a `--by` flag does to a sort key.
+fn sort_flag(flag: &str) -> Option<fn(SortKey) -> SortKey> {
+    Some(match flag {
+        "asc" => |key: SortKey| key.order(SortOrder::Ascending),
+        "desc" => |key: SortKey| key.order(SortOrder::Descending),
+        "str" => |key: SortKey| key.comparison(Comparison::String),
+        "num" => |key: SortKey| key.comparison(Comparison::Numeric),
+        "date" => |key: SortKey| key.comparison(Comparison::Date),
+        "natural" => |key: SortKey| key.comparison(Comparison::Natural),
+        "nulls-first" => |key: SortKey| key.nulls(NullOrder::First),
+        "nulls-last" => |key: SortKey| key.nulls(NullOrder::Last),
+        _ => return None,
+    })
+}
+
+fn parse_aggregations(value: &str) -> Result<Vec<Aggregation>, CliError> {
+    split_escaped(value)
+        .iter()
+        .map(|spec| {
+            // The function follows the last `:`, so that header names may
+            // contain colons, unless it is a concat whose separator does.
+            let (column, function) = match spec.find(":concat=") {
+                Some(i) => Some((&spec[..i], &spec[i + 1..])),
+                None => spec.rsplit_once(':'),
+            }
+            .ok_or_else(|| CliError::Usage(format!("aggregation `{}` needs a function", spec)))?;
+            let function = match function.split_once('=') {
+                Some(("concat", separator)) => Aggregate::Concat(separator.to_string()),
+                None => match function {
+                    "count" => Aggregate::Count,
+                    "sum" => Aggregate::Sum,
+                    "min" => Aggregate::Min,
+                    "max" => Aggregate::Max,
+                    "mean" => Aggregate::Mean,
+                    "median" => Aggregate::Median,
+                    "first" => Aggregate::First,
+                    "last" => Aggregate::Last,
+                    "count-distinct" => Aggregate::CountDistinct,
+                    "concat" => Aggregate::Concat(";".to_string()),
+                    _ => return Err(unknown_aggregate(function, spec)),
+                },
+                Some(_) => return Err(unknown_aggregate(function, spec)),
+            };
+            Ok(Aggregation::new(parse_column(column)?, function))
+        })
+        .collect()
+}
+
+/// Splits `value` on the commas not escaped as `\,`. `\\` stands for a
+/// backslash; any other backslash is kept as it is.
+fn split_escaped(value: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("at least one part");
+        match c {
+            '\\' if matches!(chars.peek(), Some(',' | '\\')) => {
+                part.extend(chars.next());
+            }
+            ',' => parts.push(String::new()),
+            _ => part.push(c),
+        }
+    }
+    parts
+}
+
+fn unknown_aggregate(function: &str, spec: &str) -> CliError {
+    CliError::Usage(format!("unknown aggregate `{}` in `{}`", function, spec))
+}
+
+fn parse_size(value: &str) -> Result<usize, CliError> {
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| CliError::Usage(format!("invalid size `{}`", value)))
+}
+
+fn parse_conflict_policy(value: &str) -> Result<ConflictPolicy, CliError> {
+    match value {
+        "left" => Ok(ConflictPolicy::KeepLeft),
+        "right" => Ok(ConflictPolicy::KeepRight),
+        "error" => Ok(ConflictPolicy::Error),
+        _ => Err(CliError::Usage(format!(
+            "conflict policy must be `left`, `right` or `error`, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn parse_join_kind(value: &str) -> Result<JoinKind, CliError> {
+    match value {
+        "inner" => Ok(JoinKind::Inner),
+        "left" => Ok(JoinKind::Left),
+        "right" => Ok(JoinKind::Right),
+        "full" => Ok(JoinKind::Full),
+        "semi" => Ok(JoinKind::Semi),
+        "anti" => Ok(JoinKind::Anti),
+        _ => Err(CliError::Usage(format!(
+            "join kind must be `inner`, `left`, `right`, `full`, `semi` or `anti`, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn parse_report(value: &str) -> Result<Report, CliError> {
+    match value {
+        "summary" => Ok(Report::Summary),
+        "csv" => Ok(Report::Csv),
+        _ => Err(CliError::Usage(format!(
+            "report must be `summary` or `csv`, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, CliError> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| CliError::Usage(format!("`{}` needs a value", flag)))
+}
+
+fn parse_delimiter(value: &str) -> Result<char, CliError> {
+    match value {
+        "\\t" | "tab" => Ok('\t'),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(CliError::Usage(format!(
+                    "delimiter must be a single character, got `{}`",
+                    value
+                ))),
+            }
+        }
+    }
+}
+
+fn parse_terminator(value: &str) -> Result<LineTerminator, CliError> {
+    match value {
+        "lf" => Ok(LineTerminator::Lf),
+        "crlf" => Ok(LineTerminator::CrLf),
+        "cr" => Ok(LineTerminator::Cr),
+        _ => Err(CliError::Usage(format!(
+            "terminator must be `lf`, `crlf` or `cr`, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn parse_encoding(value: &str) -> Result<Encoding, CliError> {
+    match value {
+        "utf-8" => Ok(Encoding::Utf8),
+        "utf-16le" => Ok(Encoding::Utf16Le),
+        "utf-16be" => Ok(Encoding::Utf16Be),
+        "latin-1" => Ok(Encoding::Latin1),
+        "windows-1252" => Ok(Encoding::Windows1252),
+        _ => Err(CliError::Usage(format!(
+            "encoding must be `utf-8`, `utf-16le`, `utf-16be`, `latin-1` or \
+             `windows-1252`, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn parse_order(value: &str) -> Result<RowOrder, CliError> {
+    match value {
+        "sorted" => Ok(RowOrder::Sorted),
+        "input" => Ok(RowOrder::FirstSeen),
+        _ => Err(CliError::Usage(format!(
+            "order must be `sorted` or `input`, got `{}`",
+            value
+        ))),
+    }
+}
+
+/// Opens an input with a buffer large enough to sniff its delimiter from.
+fn open_input(input: &str) -> Result<BufReader<Box<dyn Read>>, CsvError> {
+    let reader: Box<dyn Read> = match input {
+        "-" => Box::new(io::stdin()),
+        path => Box::new(File::open(path)?),
+    };
+    Ok(BufReader::with_capacity(SNIFF_SAMPLE, reader))
+}
+
+/// The dialect `reader`'s buffered data looks like it uses, with the
+/// delimiter given on the command line if any and a header row if
+/// `--headers` was given. The line terminator is always the detected one.
+fn input_dialect<R: BufRead>(reader: &mut R, options: &Options) -> Result<Dialect, CsvError> {
+    let sniffed = match options.encoding {
+        Some(encoding) => sniff_reader_with_encoding(reader, encoding)?,
+        None => sniff_reader(reader)?,
+    };
+    let dialect = match options.delimiter {
+        Some(delimiter) => Dialect {
+            terminator: sniffed.terminator,
+            ..Dialect::new(delimiter)
+        },
+        None => sniffed,
+    };
+    Ok(Dialect {
+        has_headers: options.has_headers,
+        ..dialect
+    })
+}
+
+/// Reads inputs in `dialect` and the encoding given on the command line, if
+/// any.
+fn input_reader(dialect: Dialect, options: &Options) -> ReaderBuilder {
+    let builder = ReaderBuilder::new().dialect(dialect);
+    match options.encoding {
+        Some(encoding) => builder.encoding(encoding),
+        None => builder,
+    }
+}
+
+fn read_input(input: &str, options: &Options) -> Result<CsvData, CsvError> {
+    let mut reader = open_input(input)?;
+    let dialect = input_dialect(&mut reader, options)?;
+    let mut csv = input_reader(dialect, options).read(reader)?;
+    for projection in &options.projections {
+        csv = match projection {
+            Projection::Select(columns) => csv.select(columns)?,
+            Projection::Drop(columns) => csv.drop(columns)?,
+            Projection::Reorder(columns) => csv.reorder(columns)?,
+        };
+    }
+    Ok(csv)
+}
+
+/// Streams every input through an [`ExternalSort`], keeping the header of
+/// the first one.
+fn run_external_sort(options: &Options, memory: usize) -> Result<(), CliError> {
+    let mut headers = None;
+    let mut first_dialect = None;
+    let mut rows: Vec<Box<dyn Iterator<Item = Result<Vec<String>, CsvError>>>> = Vec::new();
+    for input in &options.inputs {
+        let open = || -> Result<_, CsvError> {
+            let mut reader = open_input(input)?;
+            let dialect = input_dialect(&mut reader, options)?;
+            Ok((input_reader(dialect, options).build(reader), dialect))
+        };
+        let (mut reader, dialect) = open().map_err(|e| CliError::Input(input.clone(), e))?;
+        first_dialect = first_dialect.or(Some(dialect));
+        if options.has_headers {
+            let first = reader.next().transpose();
+            let first = first.map_err(|e| CliError::Input(input.clone(), e))?;
+            headers = headers.or(first);
+        }
+        rows.push(Box::new(reader));
+    }
+
+    let sorted = ExternalSort::new(options.sort_keys.clone())
+        .memory_budget(memory)
+        .sort(headers.as_deref(), rows.into_iter().flatten())?;
+    let mut writer =
+        output_writer(first_dialect.unwrap_or_default(), options).build(open_output(options)?);
+    if let Some(headers) = &headers {
+        writer.write_record(headers)?;
+    }
+    for row in sorted {
+        writer.write_record(&row?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), CliError> {
+    if let Some(memory) = options.memory {
+        return run_external_sort(options, memory);
+    }
+    let csvs = options
+        .inputs
+        .iter()
+        .map(|input| read_input(input, options).map_err(|e| CliError::Input(input.clone(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Inputs read in different detected dialects, or with different line
+    // endings, are all written in the first one's.
+    let dialect = csvs[0].dialect();
+    let delimiter = dialect.delimiter;
+    let csvs: Vec<CsvData> = csvs.iter().map(|csv| csv.with_dialect(dialect)).collect();
+
+    let keys = &options.keys;
+    if let Some(report) = options.report {
+        let diff = csvs[0].diff_by_key(&csvs[1], keys)?;
+        return match report {
+            Report::Summary => {
+                let bytes = encode_text(&diff.to_string(), options)?;
+                open_output(options)?
+                    .write_all(&bytes)
+                    .map_err(|e| CliError::Csv(e.into()))
+            }
+            Report::Csv => write_output(&diff.to_csv(delimiter), options),
+        };
+    }
+    let policy = options.conflict_policy;
+    let result = match options.command {
+        Command::Union | Command::Intersect if !keys.is_empty() => {
+            let (first, rest) = csvs.split_first().unwrap();
+            rest.iter().try_fold(first.clone(), |acc, csv| {
+                if options.command == Command::Union {
+                    acc.union_by_key(csv, keys, policy)
+                } else {
+                    acc.intersection_by_key(csv, keys, policy)
+                }
+            })?
+        }
+        Command::Diff if !keys.is_empty() => csvs[0].difference_by_key(&csvs[1], keys)?,
+        Command::Except => {
+            let (first, rest) = csvs.split_first().unwrap();
+            rest.iter()
+                .try_fold(first.clone(), |acc, csv| match keys.is_empty() {
+                    true => acc.except_with(csv, &options.set_options),
+                    false => acc.except_by_key(csv, keys),
+                })?
+        }
+        Command::Union => union_all_with(&csvs, delimiter, 0, &options.set_options)?,
+        Command::Intersect => intersection_all_with(&csvs, &options.set_options)?,
+        Command::Diff => difference_all_with(&csvs, &options.set_options)?,
+        Command::Join => csvs[0].join(&csvs[1], keys, &options.join_options)?,
+        Command::Transpose => csvs[0].transpose(),
+        Command::Filter => {
+            let expression = options.expression.as_deref().unwrap_or_default();
+            let filtered = csvs
+                .iter()
+                .map(|csv| csv.filter_expr(expression))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| CliError::Expression(expression.to_string(), e))?;
+            union_all(&filtered, delimiter, 0)?
+        }
+        Command::Sort => union_all(&csvs, delimiter, 0)?.sort_by(&options.sort_keys)?,
+        Command::GroupBy => union_all(&csvs, delimiter, 0)?
+            .group_by(keys)
+            .agg(&options.aggregations)?,
+        Command::Cat => match options.set_options.semantics {
+            Semantics::Bag => union_all(&csvs, delimiter, 0)?,
+            Semantics::Distinct => union_all_with(
+                &csvs,
+                delimiter,
+                0,
+                &options.set_options.clone().order(RowOrder::FirstSeen),
+            )?,
+        },
+    };
+
+    write_output(&result, options)
+}
+
+fn open_output(options: &Options) -> Result<Box<dyn Write>, CliError> {
+    Ok(match &options.output {
+        Some(path) => Box::new(File::create(path).map_err(CsvError::from)?),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+/// Writes in `dialect`, with the line terminator given on the command line
+/// if any, and in the output encoding.
+fn output_writer(dialect: Dialect, options: &Options) -> WriterBuilder {
+    let dialect = Dialect {
+        terminator: options.terminator.unwrap_or(dialect.terminator),
+        ..dialect
+    };
+    WriterBuilder::new()
+        .dialect(dialect)
+        .encoding(options.output_encoding)
+        .bom(options.bom)
+}
+
+/// `text` in the output encoding, after a byte order mark if `--bom` was
+/// given, for output that is not CSV.
+fn encode_text(text: &str, options: &Options) -> Result<Vec<u8>, CsvError> {
+    let encoding = options.output_encoding;
+    let bom = if options.bom { encoding.bom() } else { &[] };
+    Ok([bom, &encoding.encode(text)?].concat())
+}
+
+fn write_output(result: &CsvData, options: &Options) -> Result<(), CliError> {
+    result.to_writer(output_writer(result.dialect(), options).build(open_output(options)?))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        encode_text, input_dialect, input_reader, parse_args, CliError, Command, Options,
+        Projection, Report,
+    };
+    use rcsvtools::{
+        Aggregate, Aggregation, Column, Comparison, ConflictPolicy, CsvError, Encoding,
+        JoinAlgorithm, JoinKind, JoinOptions, LineTerminator, NullOrder, RowOrder, Semantics,
+        SetOptions, SortKey, SortOrder,
+    };
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args(&[
+            "union",
+            "-d",
+            ";",
+            "--terminator",
+            "crlf",
+            "--encoding",
+            "latin-1",
+            "--output-encoding",
+            "utf-16le",
+            "--bom",
+            "-o",
+            "out.csv",
+            "a.csv",
+            "b.csv",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                command: Command::Union,
+                delimiter: Some(';'),
+                terminator: Some(LineTerminator::CrLf),
+                encoding: Some(Encoding::Latin1),
+                output_encoding: Encoding::Utf16Le,
+                bom: true,
+                output: Some("out.csv".to_string()),
+                has_headers: false,
+                set_options: SetOptions::default(),
+                keys: Vec::new(),
+                conflict_policy: ConflictPolicy::KeepLeft,
+                report: None,
+                join_options: JoinOptions::default(),
+                projections: Vec::new(),
+                expression: None,
+                sort_keys: Vec::new(),
+                aggregations: Vec::new(),
+                memory: None,
+                inputs: vec!["a.csv".to_string(), "b.csv".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_except() {
+        let options = parse_args(&args(&["minus", "a.csv", "b.csv"])).unwrap();
+        assert_eq!(options.command, Command::Except);
+    }
+
+    #[test]
+    fn test_parse_args_projections() {
+        let options = parse_args(&args(&[
+            "cat",
+            "--select",
+            "0-2,first-name,4-",
+            "--drop",
+            "1",
+            "--reorder",
+            "first-name",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.projections,
+            vec![
+                Projection::Select(vec![
+                    Column::from(0..3),
+                    Column::from("first-name"),
+                    Column::from(4..)
+                ]),
+                Projection::Drop(vec![Column::Index(1)]),
+                Projection::Reorder(vec![Column::from("first-name")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_args_filter() {
+        let options = parse_args(&args(&["filter", "-H", "amount > 1", "a.csv"])).unwrap();
+        assert_eq!(options.expression.as_deref(), Some("amount > 1"));
+        assert_eq!(options.inputs, vec!["a.csv"]);
+        assert!(matches!(
+            parse_args(&args(&["filter", "-H"])),
+            Err(CliError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_expression_error_points_at_token() {
+        let e = CliError::Expression(
+            "amount >".to_string(),
+            CsvError::Expression {
+                position: 9,
+                message: "expected a column or a value, found end of expression".to_string(),
+            },
+        );
+        assert_eq!(
+            e.to_string(),
+            "invalid expression at position 9: expected a column or a value, \
+             found end of expression\n    amount >\n            ^"
+        );
+    }
+
+    #[test]
+    fn test_parse_args_sort() {
+        let options = parse_args(&args(&[
+            "sort",
+            "--by",
+            "amount:num:desc:nulls-first,2-3:natural,name",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.sort_keys,
+            vec![
+                SortKey::new("amount")
+                    .comparison(Comparison::Numeric)
+                    .order(SortOrder::Descending)
+                    .nulls(NullOrder::First),
+                SortKey::new(2..4).comparison(Comparison::Natural),
+                SortKey::new("name"),
+            ]
+        );
+
+        let options = parse_args(&args(&["sort", "--by", "a\\,b:desc,time:utc:num:asc"])).unwrap();
+        assert_eq!(
+            options.sort_keys,
+            vec![
+                SortKey::new("a,b").order(SortOrder::Descending),
+                SortKey::new("time:utc").comparison(Comparison::Numeric),
+            ]
+        );
+
+        let options = parse_args(&args(&["sort", "--by", "0", "--memory", "16M"])).unwrap();
+        assert_eq!(options.memory, Some(16 << 20));
+    }
+
+    #[test]
+    fn test_parse_args_groupby() {
+        let options = parse_args(&args(&[
+            "groupby",
+            "-k",
+            "region",
+            "--agg",
+            "amount:sum,2-3:count-distinct,name:concat=|",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::GroupBy);
+        assert_eq!(options.keys, vec![Column::from("region")]);
+        assert_eq!(
+            options.aggregations,
+            vec![
+                Aggregation::new("amount", Aggregate::Sum),
+                Aggregation::new(2..4, Aggregate::CountDistinct),
+                Aggregation::new("name", Aggregate::Concat("|".to_string())),
+            ]
+        );
+
+        let options = parse_args(&args(&[
+            "groupby",
+            "--agg",
+            "name:concat=\\,,time:utc:max,a\\\\b:concat=:,c:concat",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.aggregations,
+            vec![
+                Aggregation::new("name", Aggregate::Concat(",".to_string())),
+                Aggregation::new("time:utc", Aggregate::Max),
+                Aggregation::new("a\\b", Aggregate::Concat(":".to_string())),
+                Aggregation::new("c", Aggregate::Concat(";".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_args_join() {
+        let options = parse_args(&args(&[
+            "join", "-k", "id", "--how", "anti", "--sorted", "a.csv", "b.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Join);
+        assert_eq!(
+            options.join_options,
+            JoinOptions::default()
+                .kind(JoinKind::Anti)
+                .algorithm(JoinAlgorithm::SortMerge)
+        );
+    }
+
+    #[test]
+    fn test_parse_args_defaults_to_stdin() {
+        let options = parse_args(&args(&["cat", "--headers", "-d", "tab"])).unwrap();
+        assert_eq!(options.inputs, vec!["-"]);
+        assert_eq!(options.delimiter, Some('\t'));
+        assert!(options.has_headers);
+    }
+
+    #[test]
+    fn test_input_dialect_keeps_text_prefixes() {
+        let options = parse_args(&args(&["cat"])).unwrap();
+        let mut input = "id,code\n1,'00123\n2,'00456\n".as_bytes();
+        let dialect = input_dialect(&mut input, &options).unwrap();
+        assert_eq!(dialect.quote, '"');
+        let csv = input_reader(dialect, &options).read(input).unwrap();
+        assert_eq!(csv.into_iter().last().unwrap(), ["2", "'00456"]);
+    }
+
+    #[test]
+    fn test_encode_text() {
+        let options = parse_args(&args(&["diff"])).unwrap();
+        assert_eq!(
+            encode_text("\u{e9}\n", &options).unwrap(),
+            "\u{e9}\n".as_bytes()
+        );
+        let options =
+            parse_args(&args(&["diff", "--output-encoding", "utf-16le", "--bom"])).unwrap();
+        assert_eq!(
+            encode_text("\u{e9}\n", &options).unwrap(),
+            [0xFF, 0xFE, 0xE9, 0x00, 0x0A, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_parse_args_order() {
+        let options = parse_args(&args(&["union", "--order", "input", "--distinct"])).unwrap();
+        assert_eq!(options.set_options.order, RowOrder::FirstSeen);
+        assert_eq!(options.set_options.semantics, Semantics::Distinct);
+        assert!(matches!(
+            parse_args(&args(&["union", "--order", "random"])),
+            Err(CliError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_args_keys() {
+        let options = parse_args(&args(&[
+            "union",
+            "-k",
+            "id,2",
+            "--on-conflict",
+            "error",
+            "a.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.keys, vec![Column::from("id"), Column::Index(2)]);
+        assert_eq!(options.conflict_policy, ConflictPolicy::Error);
+
+        let options = parse_args(&args(&[
+            "diff", "-k", "id", "--report", "csv", "a.csv", "b.csv",
+        ]))
+        .unwrap();
+        assert_eq!(options.report, Some(Report::Csv));
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        for bad in [
+            vec![],
+            vec!["frobnicate"],
+            vec!["union", "-d"],
+            vec!["union", "-d", "ab"],
+            vec!["union", "--bogus"],
+            vec!["cat", "--select", "4-2"],
+            vec!["cat", "--order", "sorted"],
+            vec!["cat", "--distinct", "--order", "input"],
+            vec!["union", "--terminator", "windows"],
+            vec!["union", "--encoding", "ascii"],
+            vec!["union", "-", "-"],
+            vec!["transpose", "a.csv", "b.csv"],
+            vec!["diff", "-k", "id", "a.csv", "b.csv", "c.csv"],
+            vec!["union", "--on-conflict", "both"],
+            vec!["join", "a.csv", "b.csv"],
+            vec!["sort", "a.csv"],
+            vec!["sort", "--by", "a:up"],
+            vec!["groupby", "-k", "a"],
+            vec!["groupby", "--agg", "amount"],
+            vec!["groupby", "--agg", "amount:total"],
+            vec!["groupby", "--agg", "amount:sum=,"],
+            vec!["sort", "--by", "a", "--memory", "lots"],
+            vec!["cat", "--memory", "1K"],
+            vec!["join", "-k", "id", "a.csv"],
+            vec!["join", "-k", "id", "--how", "outer", "a.csv", "b.csv"],
+            vec!["diff", "--report", "summary", "a.csv", "b.csv"],
+            vec!["diff", "-k", "id", "--report", "json", "a.csv", "b.csv"],
+        ] {
+            assert!(
+                matches!(parse_args(&args(&bad)), Err(CliError::Usage(_))),
+                "{:?}",
+                bad
+            );
+        }
+        assert!(matches!(
+            parse_args(&args(&["diff", "--help"])),
+            Err(CliError::Help)
+        ));
+    }
+}
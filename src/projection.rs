@@ -0,0 +1,125 @@
+//! Picking, dropping and moving columns.
+
+use crate::column::Column;
+use crate::csvdata::CsvData;
+use crate::error::CsvError;
+
+impl CsvData {
+    /// Only the given columns, in the given order. A column listed twice is
+    /// copied twice. Fails with [`CsvError::EmptyInput`] if no column is
+    /// left.
+    pub fn select(&self, columns: &[Column]) -> Result<CsvData, CsvError> {
+        self.project_some(&self.column_indexes(columns)?)
+    }
+
+    /// Every column except the given ones. Fails with
+    /// [`CsvError::EmptyInput`] if no column is left.
+    pub fn drop(&self, columns: &[Column]) -> Result<CsvData, CsvError> {
+        let dropped = self.column_indexes(columns)?;
+        let kept: Vec<usize> = (0..self.line_width)
+            .filter(|i| !dropped.contains(i))
+            .collect();
+        self.project_some(&kept)
+    }
+
+    /// The given columns first, in the given order, followed by the others
+    /// in their current order.
+    pub fn reorder(&self, columns: &[Column]) -> Result<CsvData, CsvError> {
+        let mut order = Vec::new();
+        for index in self.column_indexes(columns)? {
+            if !order.contains(&index) {
+                order.push(index);
+            }
+        }
+        let rest: Vec<usize> = (0..self.line_width)
+            .filter(|i| !order.contains(i))
+            .collect();
+        order.extend(rest);
+        Ok(self.project(&order))
+    }
+
+    fn project_some(&self, indexes: &[usize]) -> Result<CsvData, CsvError> {
+        match indexes.is_empty() {
+            true => Err(CsvError::EmptyInput),
+            false => Ok(self.project(indexes)),
+        }
+    }
+
+    pub(crate) fn project(&self, indexes: &[usize]) -> CsvData {
+        let pick =
+            |row: &[String]| -> Vec<String> { indexes.iter().map(|&i| row[i].clone()).collect() };
+        CsvData {
+            data: self.rows().flat_map(pick).collect(),
+            dialect: self.dialect,
+            line_width: indexes.len(),
+            headers: self.headers.as_deref().map(pick),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::Column;
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+
+    fn wide() -> CsvData {
+        CsvData::from_text_with_headers("a,b,c,d,e\n1,2,3,4,5\n6,7,8,9,10".to_string(), ',')
+            .unwrap()
+    }
+
+    #[test]
+    fn test_select() {
+        let result = wide()
+            .select(&[Column::from("d"), Column::from(0..2), Column::Index(0)])
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["d", "a", "b", "a"]);
+        assert_eq!(result.line_width, 4);
+        assert_eq!(result.data, vec!["4", "1", "2", "1", "9", "6", "7", "6"]);
+
+        let result = wide().select(&[Column::from(3..)]).unwrap();
+        assert_eq!(result.data, vec!["4", "5", "9", "10"]);
+        let result = wide().select(&[Column::from(3..=usize::MAX)]).unwrap();
+        assert_eq!(result.data, vec!["4", "5", "9", "10"]);
+    }
+
+    #[test]
+    fn test_drop_and_reorder() {
+        let result = wide()
+            .drop(&[Column::from(1..=2), Column::from("e")])
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["a", "d"]);
+        assert_eq!(result.line_width, 2);
+        assert_eq!(result.data, vec!["1", "4", "6", "9"]);
+
+        let result = wide()
+            .reorder(&[Column::from("e"), Column::Index(2), Column::from("e")])
+            .unwrap();
+        assert_eq!(result.headers().unwrap(), ["e", "c", "a", "b", "d"]);
+        assert_eq!(result.data[..5], ["5", "3", "1", "2", "4"]);
+    }
+
+    #[test]
+    fn test_projection_unknown_column() {
+        for columns in [
+            vec![Column::from("z")],
+            vec![Column::Index(5)],
+            vec![Column::from(4..6)],
+            vec![Column::from(5..)],
+        ] {
+            assert!(matches!(
+                wide().select(&columns),
+                Err(CsvError::UnknownColumn { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_projection_without_columns() {
+        assert!(matches!(wide().select(&[]), Err(CsvError::EmptyInput)));
+        assert!(matches!(
+            wide().drop(&[Column::from(0..)]),
+            Err(CsvError::EmptyInput)
+        ));
+    }
+}
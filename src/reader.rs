@@ -0,0 +1,395 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::csvdata::CsvData;
+use crate::dialect::{Dialect, LineTerminator, Trim};
+use crate::encoding::{detect_encoding, Decoder, Encoding};
+use crate::error::CsvError;
+use crate::tokenizer::{collect_records, RecordParser};
+
+/// Reads records one at a time from any [`Read`] source, so files larger than
+/// memory can be filtered or transformed row by row.
+///
+/// Rows have the same shape as the ones yielded by `CsvDataIterator`, but as
+/// the reader never sees the whole input they are not padded to a common
+/// width. Blank lines are skipped, like in `CsvData::from_file`.
+///
+/// The header row, if the dialect has one, is yielded as the first record.
+///
+/// Unless an encoding was chosen, it is detected from the first chunk of
+/// input read. Input detected as UTF-8 because that chunk was all ASCII
+/// switches to Windows-1252 at the first byte that is not UTF-8; once
+/// anything else has been read, such a byte is a [`CsvError::Decode`].
+/// Choosing the encoding avoids guessing.
+pub struct CsvReader<R: Read> {
+    reader: BufReader<R>,
+    parser: RecordParser,
+    encoding: Option<Encoding>,
+    decoder: Option<Decoder>,
+    /// The last chunk of input read, decoded.
+    text: String,
+    /// Records completed by the last chunk read but not yielded yet.
+    pending: VecDeque<Vec<String>>,
+    done: bool,
+    /// Whether the next record trimmed is the header row.
+    at_headers: bool,
+    trim: Trim,
+}
+
+impl<R: Read> CsvReader<R> {
+    pub fn new(reader: R, delimiter: char) -> Self {
+        ReaderBuilder::new().delimiter(delimiter).build(reader)
+    }
+
+    /// Decodes and parses the next chunk of input, queueing the records it
+    /// completes.
+    fn read_chunk(&mut self) -> Result<(), CsvError> {
+        let bytes = self.reader.fill_buf()?;
+        let decoder = self.decoder.get_or_insert_with(|| {
+            Decoder::new(self.encoding.unwrap_or_else(|| detect_encoding(bytes)))
+        });
+        if bytes.is_empty() {
+            self.done = true;
+            decoder.finish()?;
+            self.pending.extend(self.parser.finish()?);
+            return Ok(());
+        }
+        let read = bytes.len();
+        self.text.clear();
+        let mut decoded = decoder.decode(bytes, &mut self.text);
+        if decoded.is_err() && self.encoding.is_none() && decoder.fall_back() {
+            decoded = decoder.decode(&[], &mut self.text);
+        }
+        decoded?;
+        self.reader.consume(read);
+        for c in self.text.chars() {
+            self.pending.extend(self.parser.feed(c)?);
+        }
+        Ok(())
+    }
+
+    fn trim(&mut self, record: Vec<String>) -> Vec<String> {
+        let trim = match std::mem::replace(&mut self.at_headers, false) {
+            true => self.trim.headers(),
+            false => self.trim.fields(),
+        };
+        match trim {
+            true => record.iter().map(|cell| cell.trim().to_string()).collect(),
+            false => record,
+        }
+    }
+}
+
+impl CsvReader<File> {
+    pub fn from_path<P: AsRef<Path>>(path: P, delimiter: char) -> Result<Self, CsvError> {
+        ReaderBuilder::new().delimiter(delimiter).open(path)
+    }
+}
+
+/// Sets up a [`CsvReader`], or reads a whole [`CsvData`], in any
+/// [`Dialect`] and [`Encoding`]. Blank lines are skipped, like in
+/// `CsvData::from_file`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ReaderBuilder {
+    dialect: Dialect,
+    preserve_terminator: bool,
+    encoding: Option<Encoding>,
+}
+
+impl ReaderBuilder {
+    /// A builder for the default dialect: comma-delimited, no header row.
+    pub fn new() -> Self {
+        ReaderBuilder::default()
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.dialect.delimiter = delimiter;
+        self
+    }
+
+    pub fn quote(mut self, quote: char) -> Self {
+        self.dialect.quote = quote;
+        self
+    }
+
+    /// Makes the character after `escape` inside a quoted field literal.
+    pub fn escape(mut self, escape: Option<char>) -> Self {
+        self.dialect.escape = escape;
+        self
+    }
+
+    /// Skips lines starting with `comment`.
+    pub fn comment(mut self, comment: Option<char>) -> Self {
+        self.dialect.comment = comment;
+        self
+    }
+
+    /// Terminator the table is written with. Any line break ends a record on
+    /// read, whatever this is set to.
+    pub fn terminator(mut self, terminator: LineTerminator) -> Self {
+        self.dialect.terminator = terminator;
+        self
+    }
+
+    /// Makes a table read with [`read`](Self::read) remember the first line
+    /// break of the input as its terminator, so it is written back with the
+    /// line endings it had. Input without any line break keeps the
+    /// [`terminator`](Self::terminator) set here.
+    pub fn preserve_terminator(mut self, preserve: bool) -> Self {
+        self.preserve_terminator = preserve;
+        self
+    }
+
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.dialect.has_headers = has_headers;
+        self
+    }
+
+    pub fn trim(mut self, trim: Trim) -> Self {
+        self.dialect.trim = trim;
+        self
+    }
+
+    /// Reads the input in `encoding` instead of the one it is detected to
+    /// be in. A byte order mark of that encoding is dropped either way.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    fn parser(&self) -> RecordParser {
+        RecordParser::new(&self.dialect).skip_blank_lines()
+    }
+
+    pub fn build<R: Read>(&self, reader: R) -> CsvReader<R> {
+        CsvReader {
+            reader: BufReader::new(reader),
+            parser: self.parser(),
+            encoding: self.encoding,
+            decoder: None,
+            text: String::new(),
+            pending: VecDeque::new(),
+            done: false,
+            at_headers: self.dialect.has_headers,
+            trim: self.dialect.trim,
+        }
+    }
+
+    /// Opens the file at `path` and reads from it.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<CsvReader<File>, CsvError> {
+        Ok(self.build(File::open(path)?))
+    }
+
+    /// Reads a whole table, which remembers the dialect it was read with.
+    pub fn read<R: Read>(&self, mut reader: R) -> Result<CsvData, CsvError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let encoding = self.encoding.unwrap_or_else(|| detect_encoding(&bytes));
+        self.read_text(&encoding.decode(&bytes)?)
+    }
+
+    pub fn read_path<P: AsRef<Path>>(&self, path: P) -> Result<CsvData, CsvError> {
+        self.read(File::open(path)?)
+    }
+
+    pub fn read_text(&self, text: &str) -> Result<CsvData, CsvError> {
+        let mut parser = self.parser();
+        let records = collect_records(&mut parser, text)?;
+        let mut dialect = self.dialect;
+        if self.preserve_terminator {
+            dialect.terminator = parser.terminator().unwrap_or(dialect.terminator);
+        }
+        CsvData::from_records(records, dialect)
+    }
+}
+
+impl<R: Read> Iterator for CsvReader<R> {
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(self.trim(record)));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.read_chunk() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csvdata::CsvData;
+    use std::io::{BufReader, Read};
+
+    use crate::dialect::{Dialect, LineTerminator, Trim};
+    use crate::encoding::Encoding;
+    use crate::error::CsvError;
+    use crate::reader::{CsvReader, ReaderBuilder};
+
+    fn rows(text: &str) -> Vec<Vec<String>> {
+        CsvReader::new(text.as_bytes(), ',')
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(rows("a,b\nc,d\n"), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        assert_eq!(rows("a,b\n\nc"), vec![vec!["a", "b"], vec!["c"]]);
+    }
+
+    #[test]
+    fn test_quoted_newline() {
+        assert_eq!(
+            rows("\"a\nb\",c\nd,e\n"),
+            vec![vec!["a\nb", "c"], vec!["d", "e"]]
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(rows("").is_empty());
+    }
+
+    #[test]
+    fn test_line_endings() {
+        assert_eq!(
+            rows("a,b\r\n\"c\rd\",e\rf\rg\r\n"),
+            vec![vec!["a", "b"], vec!["c\rd", "e"], vec!["f"], vec!["g"]]
+        );
+    }
+
+    #[test]
+    fn test_preserve_terminator() {
+        let builder = ReaderBuilder::new().terminator(LineTerminator::Cr);
+        let text = "a,b\r\nc,d\ne,f";
+        let csv = builder.read_text(text).unwrap();
+        assert_eq!(csv.data, ["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(csv.dialect().terminator, LineTerminator::Cr);
+
+        let builder = builder.preserve_terminator(true);
+        let csv = builder.read_text(text).unwrap();
+        assert_eq!(csv.dialect().terminator, LineTerminator::CrLf);
+        let csv = builder.read_text("a,b").unwrap();
+        assert_eq!(csv.dialect().terminator, LineTerminator::Cr);
+    }
+
+    #[test]
+    fn test_matches_csv_data_iterator() {
+        let text = "x,\"y,z\"\n1,2\n";
+        let csv = CsvData::from_text(text.trim_end().to_string(), ',').unwrap();
+        let expected: Vec<Vec<String>> = csv.into_iter().collect();
+        assert_eq!(rows(text), expected);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes: &[u8] = &[b'a', b',', 0xff, b'\n'];
+        let result: Result<Vec<_>, _> = ReaderBuilder::new()
+            .encoding(Encoding::Utf8)
+            .build(bytes)
+            .collect();
+        assert!(matches!(result, Err(CsvError::Decode { offset: 2, .. })));
+        // Detected as Windows-1252 otherwise.
+        assert_eq!(rows("a,\u{ff}\n"), vec![vec!["a", "\u{ff}"]]);
+        let result: Vec<_> = CsvReader::new(bytes, ',')
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(result, vec![vec!["a", "\u{ff}"]]);
+
+        // Windows-1252 past an all-ASCII first chunk.
+        let first: &[u8] = b"id,name\n";
+        let rest: &[u8] = b"Jos\xe9,\x80\n";
+        let rows: Vec<Vec<String>> = CsvReader::new(first.chain(rest), ',')
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["id", "name"], vec!["Jos\u{e9}", "\u{20ac}"]]
+        );
+        // But not once UTF-8 has been seen.
+        let first = "\u{e9},a\n".as_bytes();
+        let result: Result<Vec<_>, _> = CsvReader::new(first.chain(rest), ',').collect();
+        assert!(matches!(result, Err(CsvError::Decode { offset: 8, .. })));
+    }
+
+    #[test]
+    fn test_encodings() {
+        let text = "id,name\r\n1,Jos\u{e9}\r\n";
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Windows1252] {
+            let bytes = [encoding.bom(), &encoding.encode(text).unwrap()].concat();
+            // Small chunks split characters between reads.
+            let rows: Vec<Vec<String>> =
+                CsvReader::new(BufReader::with_capacity(3, &bytes[..]), ',')
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+            assert_eq!(rows, vec![vec!["id", "name"], vec!["1", "Jos\u{e9}"]]);
+
+            let csv = ReaderBuilder::new()
+                .has_headers(true)
+                .read(&bytes[..])
+                .unwrap();
+            assert_eq!(csv.data, ["1", "Jos\u{e9}"]);
+        }
+        let csv = ReaderBuilder::new()
+            .encoding(Encoding::Latin1)
+            .read(&b"\x80"[..])
+            .unwrap();
+        assert_eq!(csv.data, ["\u{80}"]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let result: Result<Vec<_>, _> = CsvReader::new("a\n\"b".as_bytes(), ',').collect();
+        assert!(matches!(
+            result,
+            Err(CsvError::Parse { line: 2, column: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_reader_builder() {
+        let builder = ReaderBuilder::new()
+            .delimiter('|')
+            .comment(Some('#'))
+            .has_headers(true)
+            .trim(Trim::All);
+        let text = "# exported\n id | name \n1|'a'\n";
+        let rows: Vec<Vec<String>> = builder
+            .build(text.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, vec![vec!["id", "name"], vec!["1", "'a'"]]);
+
+        let csv = builder.quote('\'').read_text(text).unwrap();
+        assert_eq!(csv.headers().unwrap(), ["id", "name"]);
+        assert_eq!(csv.data, ["1", "a"]);
+        assert_eq!(
+            csv.dialect(),
+            Dialect {
+                quote: '\'',
+                comment: Some('#'),
+                has_headers: true,
+                trim: Trim::All,
+                ..Dialect::new('|')
+            }
+        );
+    }
+}
@@ -0,0 +1,521 @@
+//! A small regular expression matcher for the `=~` operator of the filter
+//! language.
+//!
+//! Patterns are compiled to a Thompson NFA that is run over the text in a
+//! single pass, keeping every live state at once, so matching takes time
+//! proportional to the length of the text times the size of the pattern and
+//! never backtracks or recurses per character.
+//!
+//! Supported: literals, `.`, character classes with ranges and negation,
+//! the escapes `\d \w \s \D \W \S \t \n \r`, the anchors `^` and `$`,
+//! groups (`(...)` and `(?:...)`), alternation and the greedy quantifiers
+//! `* + ? {n} {n,} {n,m}`. A pattern matches if it matches anywhere in the
+//! text unless it is anchored.
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// One instruction of the compiled NFA. `Split` continues at both targets.
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Largest number of instructions a pattern may compile to. Counted
+/// repetitions copy what they repeat, so `(a{1000}){1000}` would otherwise
+/// take a million.
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+}
+
+/// Why a pattern was rejected, and the 0-based position of the character
+/// at fault.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct RegexError {
+    pub(crate) position: usize,
+    pub(crate) message: String,
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("unmatched `)`"));
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let input: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=input.len() {
+            // A match may start anywhere, so every position starts a thread.
+            if self.add(&mut current, 0, pos, &input) {
+                return true;
+            }
+            let Some(&c) = input.get(pos) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.list {
+                let step = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class { ranges, negated } => {
+                        ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+                    }
+                    _ => false,
+                };
+                if step && self.add(&mut next, pc + 1, pos + 1, &input) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps, splits and
+    /// anchors at `pos`. Returns whether it reaches a match.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, input: &[char]) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(target) => stack.push(target),
+                Inst::Split(first, second) => stack.extend([second, first]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == input.len() => stack.push(pc + 1),
+                Inst::Char(_) | Inst::Any | Inst::Class { .. } => threads.list.push(pc),
+                Inst::Start | Inst::End => {}
+            }
+        }
+        false
+    }
+}
+
+/// The states live at one position of the text.
+struct Threads {
+    /// States that consume a character, in no particular order.
+    list: Vec<usize>,
+    /// States already reached at this position, including jumps and splits.
+    seen: Vec<bool>,
+    reached: Vec<usize>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            list: Vec::new(),
+            seen: vec![false; size],
+            reached: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.reached.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        for pc in self.reached.drain(..) {
+            self.seen[pc] = false;
+        }
+        self.list.clear();
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn error(&self, message: &str) -> RegexError {
+        RegexError {
+            position: self.pos.min(self.chars.len()),
+            message: message.to_string(),
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, RegexError> {
+        let mut alternatives = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.concat()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Node::Alternation(alternatives),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifiers(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let start = self.pos;
+        match self.next() {
+            Some('(') => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let node = self.alternation()?;
+                match self.next() {
+                    Some(')') => Ok(node),
+                    _ => Err(RegexError {
+                        position: start,
+                        message: "unclosed group".to_string(),
+                    }),
+                }
+            }
+            Some('[') => self.class(start),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => match self.escape()? {
+                Escape::Char(c) => Ok(Node::Char(c)),
+                Escape::Class(ranges, negated) => Ok(Node::Class {
+                    ranges: ranges.to_vec(),
+                    negated,
+                }),
+            },
+            Some('*' | '+' | '?') => {
+                self.pos = start;
+                Err(self.error("nothing to repeat"))
+            }
+            Some(c) => Ok(Node::Char(c)),
+            None => Err(self.error("unexpected end of pattern")),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Escape, RegexError> {
+        Ok(match self.next() {
+            Some('d') => Escape::Class(DIGIT, false),
+            Some('D') => Escape::Class(DIGIT, true),
+            Some('w') => Escape::Class(WORD, false),
+            Some('W') => Escape::Class(WORD, true),
+            Some('s') => Escape::Class(SPACE, false),
+            Some('S') => Escape::Class(SPACE, true),
+            Some('t') => Escape::Char('\t'),
+            Some('n') => Escape::Char('\n'),
+            Some('r') => Escape::Char('\r'),
+            Some(c) => Escape::Char(c),
+            None => return Err(self.error("trailing backslash")),
+        })
+    }
+
+    fn class(&mut self, start: usize) -> Result<Node, RegexError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let low = match self.next() {
+                None => {
+                    return Err(RegexError {
+                        position: start,
+                        message: "unclosed character class".to_string(),
+                    })
+                }
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(class, false) => {
+                        ranges.extend_from_slice(class);
+                        first = false;
+                        continue;
+                    }
+                    Escape::Class(_, true) => {
+                        self.pos -= 2;
+                        return Err(self.error("negated escapes are not supported in a class"));
+                    }
+                },
+                Some(c) => c,
+            };
+            first = false;
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                ranges.push((low, low));
+                continue;
+            }
+            self.pos += 1;
+            let high = match self.next() {
+                Some('\\') => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(..) => return Err(self.error("invalid class range")),
+                },
+                Some(c) => c,
+                None => unreachable!("checked by is_range"),
+            };
+            if high < low {
+                self.pos -= 1;
+                return Err(self.error("invalid class range"));
+            }
+            ranges.push((low, high));
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn quantifiers(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.counted()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(node),
+                },
+                Some(c @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(node),
+            };
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}` and moves past it. A `{` that does
+    /// not start one of those is left alone and matched literally.
+    fn counted(&mut self) -> Result<Option<(usize, Option<usize>)>, RegexError> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let Some(end) = rest.find('}') else {
+            return Ok(None);
+        };
+        let body = &rest[..end];
+        let number = |s: &str| s.parse::<usize>().ok();
+        let bounds = match body.split_once(',') {
+            None => number(body).map(|n| (n, Some(n))),
+            Some((low, "")) => number(low).map(|n| (n, None)),
+            Some((low, high)) => number(low).zip(number(high)).map(|(l, h)| (l, Some(h))),
+        };
+        match bounds {
+            Some((low, Some(high))) if high < low => Err(self.error("invalid repeat bounds")),
+            Some(bounds) => {
+                self.pos += body.chars().count() + 2;
+                Ok(Some(bounds))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(&'static [(char, char)], bool),
+}
+
+/// Appends the instructions for `node` to `program`.
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), RegexError> {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { ranges, negated } => program.push(Inst::Class {
+            ranges: ranges.clone(),
+            negated: *negated,
+        }),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alternation(alternatives) => {
+            let mut jumps = Vec::new();
+            let (last, rest) = alternatives
+                .split_last()
+                .expect("at least two alternatives");
+            for alternative in rest {
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(alternative, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            compile(last, program)?;
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    match program.len() > MAX_PROGRAM {
+        true => Err(RegexError {
+            position: 0,
+            message: "pattern is too large".to_string(),
+        }),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::regex::Regex;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_regex_matching() {
+        assert!(is_match("act", "inactive"));
+        assert!(!is_match("^act", "inactive"));
+        assert!(is_match("^in.*ve$", "inactive"));
+        assert!(is_match("^(foo|ba[rz])+$", "foobazbar"));
+        assert!(!is_match("^(foo|ba[rz])+$", "foobaq"));
+        assert!(is_match(r"^\d{3}-\d{2,}$", "123-4567"));
+        assert!(!is_match(r"^\d{3}-\d{2,}$", "123-4"));
+        assert!(is_match(r"^[^\s,]+@\w+\.com$", "a.b@example.com"));
+        assert!(is_match("^colou?r$", "color"));
+        assert!(is_match("^a{2}$", "aa"));
+        assert!(is_match("^x{y$", "x{y"));
+        assert!(is_match("^(a*)*$", "aaaa"));
+        assert!(is_match("^(?:ab)?$", ""));
+        assert!(is_match("[-a]", "-"));
+        assert!(is_match("^(a|b)*c|d$", "abd"));
+        assert!(!is_match("^(a|b)*c$", "abd"));
+        assert!(is_match("^a{2,3}$", "aaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+    }
+
+    #[test]
+    fn test_regex_pathological() {
+        // Exponential for a backtracking matcher.
+        let text = "a".repeat(40) + "b";
+        assert!(!is_match("^(a|a)*$", &text));
+        assert!(!is_match("^(a*)*$", &text));
+        // Deep recursion for a recursive one.
+        let text = "a".repeat(200_000);
+        assert!(!is_match("a*b", &text));
+        assert!(is_match("a*$", &text));
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        for (pattern, position) in [
+            ("(ab", 0),
+            ("ab)", 2),
+            ("*a", 0),
+            ("a|+", 2),
+            ("[ab", 0),
+            ("[z-a]", 3),
+            ("a\\", 2),
+            ("a{3,1}", 1),
+            ("(a{1000}){1000}", 0),
+        ] {
+            let error = Regex::new(pattern).unwrap_err();
+            assert_eq!(error.position, position, "{}: {}", pattern, error.message);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Reshaping tables between long and wide layouts on key columns.
+//!
+//! Unlike [`CsvData::transpose`], which flips the whole table, these move
+//! cells between rows and columns by key. The results always have a header
+//! row: columns of a table without one are named after their 0-based
+//! position.
+
+use std::collections::HashMap;
+
+use crate::column::Column;
+use crate::csvdata::CsvData;
+use crate::error::CsvError;
+use crate::groupby::Aggregate;
+
+/// Position of `key` in `keys`, appending it if it is new.
+fn position<'a>(
+    keys: &mut Vec<Vec<&'a str>>,
+    positions: &mut HashMap<Vec<&'a str>, usize>,
+    key: Vec<&'a str>,
+) -> usize {
+    *positions.entry(key.clone()).or_insert_with(|| {
+        keys.push(key);
+        keys.len() - 1
+    })
+}
+
+fn key_of<'a>(row: &'a [String], columns: &[usize]) -> Vec<&'a str> {
+    columns.iter().map(|&i| row[i].as_str()).collect()
+}
+
+impl CsvData {
+    fn header_name(&self, index: usize) -> String {
+        match &self.headers {
+            Some(headers) => headers[index].clone(),
+            None => index.to_string(),
+        }
+    }
+
+    /// Long to wide: one row per distinct `index` key and one column per
+    /// distinct `columns` key, both in order of first appearance. Every cell
+    /// combines with `agg` the `values` cells of the rows holding both keys.
+    ///
+    /// New columns are named after their `columns` key, its cells joined
+    /// with `_`. With several `values` columns, each gets its own set of new
+    /// columns, prefixed with its name as in `amount_2024`.
+    pub fn pivot(
+        &self,
+        index: &[Column],
+        columns: &[Column],
+        values: &[Column],
+        agg: Aggregate,
+    ) -> Result<CsvData, CsvError> {
+        let index = self.column_indexes(index)?;
+        let columns = self.column_indexes(columns)?;
+        let values = self.column_indexes(values)?;
+
+        let (mut row_keys, mut row_positions) = (Vec::new(), HashMap::new());
+        let (mut column_keys, mut column_positions) = (Vec::new(), HashMap::new());
+        let mut groups: HashMap<(usize, usize), Vec<&[String]>> = HashMap::new();
+        for row in self.rows() {
+            let r = position(&mut row_keys, &mut row_positions, key_of(row, &index));
+            let c = position(
+                &mut column_keys,
+                &mut column_positions,
+                key_of(row, &columns),
+            );
+            groups.entry((r, c)).or_default().push(row);
+        }
+
+        let mut data = Vec::new();
+        for (r, key) in row_keys.iter().enumerate() {
+            data.extend(key.iter().map(|cell| cell.to_string()));
+            for &value in &values {
+                for c in 0..column_keys.len() {
+                    let rows = groups.get(&(r, c)).map(Vec::as_slice).unwrap_or_default();
+                    let cells: Vec<&str> = rows.iter().map(|row| row[value].as_str()).collect();
+                    data.push(agg.apply(&cells));
+                }
+            }
+        }
+
+        let mut headers: Vec<String> = index.iter().map(|&i| self.header_name(i)).collect();
+        for &value in &values {
+            for key in &column_keys {
+                let name = key.join("_");
+                headers.push(match values.len() {
+                    1 => name,
+                    _ => format!("{}_{}", self.header_name(value), name),
+                });
+            }
+        }
+
+        Ok(CsvData {
+            data,
+            dialect: self.dialect,
+            line_width: headers.len(),
+            headers: Some(headers),
+        })
+    }
+
+    /// Wide to long: every row becomes one row per `value_vars` column,
+    /// holding the `id_vars` cells followed by a `variable` column with the
+    /// name of that column and a `value` column with its cell. Without
+    /// `value_vars`, every column that is not an `id_vars` column is used.
+    pub fn melt(&self, id_vars: &[Column], value_vars: &[Column]) -> Result<CsvData, CsvError> {
+        let ids = self.column_indexes(id_vars)?;
+        let values: Vec<usize> = if value_vars.is_empty() {
+            (0..self.line_width).filter(|i| !ids.contains(i)).collect()
+        } else {
+            self.column_indexes(value_vars)?
+        };
+        let names: Vec<String> = values.iter().map(|&i| self.header_name(i)).collect();
+
+        let mut data = Vec::new();
+        for row in self.rows() {
+            for (&value, name) in values.iter().zip(&names) {
+                data.extend(ids.iter().map(|&i| row[i].clone()));
+                data.push(name.clone());
+                data.push(row[value].clone());
+            }
+        }
+
+        let mut headers: Vec<String> = ids.iter().map(|&i| self.header_name(i)).collect();
+        headers.push("variable".to_string());
+        headers.push("value".to_string());
+        Ok(CsvData {
+            data,
+            dialect: self.dialect,
+            line_width: headers.len(),
+            headers: Some(headers),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::column::Column;
+    use crate::csvdata::CsvData;
+    use crate::error::CsvError;
+    use crate::groupby::Aggregate;
+
+    fn long() -> CsvData {
+        CsvData::from_text_with_headers(
+            "region,year,amount,units\nnorth,2023,10,1\nnorth,2024,5,2\nsouth,2024,7,3\nnorth,2024,1,4"
+                .to_string(),
+            ',',
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pivot() {
+        let wide = long()
+            .pivot(
+                &[Column::from("region")],
+                &[Column::from("year")],
+                &[Column::from("amount")],
+                Aggregate::Sum,
+            )
+            .unwrap();
+        assert_eq!(wide.headers().unwrap(), ["region", "2023", "2024"]);
+        assert_eq!(wide.line_width, 3);
+        assert_eq!(wide.data, ["north", "10", "6", "south", "", "7"]);
+    }
+
+    #[test]
+    fn test_pivot_several_values() {
+        let csv = CsvData::from_text("a,x,1,2\na,y,3,4\nb,x,5,6".to_string(), ',').unwrap();
+        let wide = csv
+            .pivot(
+                &[Column::Index(0)],
+                &[Column::Index(1)],
+                &[Column::from(2..4)],
+                Aggregate::Count,
+            )
+            .unwrap();
+        assert_eq!(wide.headers().unwrap(), ["0", "2_x", "2_y", "3_x", "3_y"]);
+        assert_eq!(
+            wide.data,
+            ["a", "1", "1", "1", "1", "b", "1", "0", "1", "0"]
+        );
+        assert_eq!(wide.delimiter(), ',');
+    }
+
+    #[test]
+    fn test_melt() {
+        let melted = long()
+            .melt(&[Column::from("region"), Column::from("year")], &[])
+            .unwrap();
+        assert_eq!(
+            melted.headers().unwrap(),
+            ["region", "year", "variable", "value"]
+        );
+        assert_eq!(melted.line_width, 4);
+        assert_eq!(
+            melted.data[..8],
+            ["north", "2023", "amount", "10", "north", "2023", "units", "1"]
+        );
+        assert_eq!(melted.data.len(), 4 * 8);
+
+        let melted = long()
+            .melt(&[Column::Index(0)], &[Column::from("units")])
+            .unwrap();
+        assert_eq!(melted.data[..3], ["north", "units", "1"]);
+        assert_eq!(melted.data.len(), 3 * 4);
+        assert!(matches!(
+            long().melt(&[Column::from("nope")], &[]),
+            Err(CsvError::UnknownColumn { .. })
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+//! Options for the row-set operations and the counted row map behind them.
+
+use std::cmp::min;
+use std::collections::HashMap;
+
+/// Order of the rows produced by a set operation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum RowOrder {
+    /// Rows sorted cell by cell.
+    #[default]
+    Sorted,
+    /// Rows in the order they were first seen, left input first.
+    FirstSeen,
+}
+
+/// How duplicate rows are treated by a set operation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Semantics {
+    /// Multiset semantics, like SQL `UNION ALL` / `INTERSECT ALL` /
+    /// `EXCEPT ALL`: a union keeps every copy, an intersection keeps the
+    /// smaller number of copies and a difference keeps the copies one side
+    /// holds in surplus.
+    #[default]
+    Bag,
+    /// Set semantics, like SQL `UNION` / `INTERSECT` / `EXCEPT`: every row
+    /// is returned at most once, and a difference only returns rows the other
+    /// side does not hold at all.
+    Distinct,
+}
+
+/// Settings shared by the `*_with` variants of the set operations. The
+/// default reproduces the plain `union` / `intersection` / `difference`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SetOptions {
+    pub order: RowOrder,
+    pub semantics: Semantics,
+}
+
+impl SetOptions {
+    pub fn order(mut self, order: RowOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn semantics(mut self, semantics: Semantics) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
+    /// Number of copies of a row to output when the bag result holds `count`.
+    pub(crate) fn copies(&self, count: i32) -> i32 {
+        match self.semantics {
+            Semantics::Bag => count,
+            Semantics::Distinct => min(count, 1),
+        }
+    }
+}
+
+/// Occurrence count of every distinct row, remembering when each row was
+/// first seen so that input order can be restored.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LineCounts {
+    lines: HashMap<Vec<String>, (usize, i32)>,
+}
+
+impl LineCounts {
+    pub(crate) fn new() -> Self {
+        LineCounts::default()
+    }
+
+    pub(crate) fn add(&mut self, line: Vec<String>, count: i32) {
+        let next = self.lines.len();
+        self.lines.entry(line).or_insert((next, 0)).1 += count;
+    }
+
+    pub(crate) fn get(&self, line: &[String]) -> Option<i32> {
+        self.lines.get(line).map(|&(_, count)| count)
+    }
+
+    /// Every distinct row with its count, in the requested order.
+    pub(crate) fn into_ordered(self, order: RowOrder) -> Vec<(Vec<String>, i32)> {
+        let mut lines: Vec<(Vec<String>, (usize, i32))> = self.lines.into_iter().collect();
+        match order {
+            RowOrder::Sorted => lines.sort_unstable_by(|a, b| a.0.cmp(&b.0)),
+            RowOrder::FirstSeen => lines.sort_unstable_by_key(|(_, (seen, _))| *seen),
+        }
+        lines
+            .into_iter()
+            .map(|(line, (_, count))| (line, count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::setops::{LineCounts, RowOrder};
+
+    fn line(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_line_counts_order() {
+        let mut counts = LineCounts::new();
+        counts.add(line(&["b"]), 1);
+        counts.add(line(&["a"]), 1);
+        counts.add(line(&["b"]), 2);
+
+        assert_eq!(counts.get(&line(&["b"])), Some(3));
+        assert_eq!(counts.get(&line(&["c"])), None);
+        assert_eq!(
+            counts.clone().into_ordered(RowOrder::Sorted),
+            vec![(line(&["a"]), 1), (line(&["b"]), 3)]
+        );
+        assert_eq!(
+            counts.into_ordered(RowOrder::FirstSeen),
+            vec![(line(&["b"]), 3), (line(&["a"]), 1)]
+        );
+    }
+}
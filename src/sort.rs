@@ -0,0 +1,369 @@
+//! Stable multi-key sorting with typed comparisons.
+
+use std::cmp::Ordering;
+
+use crate::column::Column;
+use crate::csvdata::CsvData;
+use crate::error::CsvError;
+
+/// Direction of a sort key.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// How the cells of a sort key are compared.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Comparison {
+    /// Byte-wise string order.
+    #[default]
+    String,
+    /// As numbers. Cells that are not numbers sort after all numbers
+    /// whatever the direction of the key, and among themselves by string
+    /// in that direction.
+    Numeric,
+    /// As dates: `YYYY-MM-DD` or `YYYY/MM/DD`, optionally followed by `T`
+    /// or a space and `HH:MM`, `HH:MM:SS` or `HH:MM:SS.fff`, and an optional
+    /// `Z`. Cells that are not dates sort after all dates, like
+    /// non-numbers for [`Numeric`](Comparison::Numeric).
+    Date,
+    /// Runs of digits compare as numbers and the rest as strings, so that
+    /// `file2` sorts before `file10`.
+    Natural,
+}
+
+/// Where empty cells go, whatever the direction of the key.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum NullOrder {
+    First,
+    #[default]
+    Last,
+}
+
+/// One column to sort on and how.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SortKey {
+    pub column: Column,
+    pub order: SortOrder,
+    pub comparison: Comparison,
+    pub nulls: NullOrder,
+}
+
+impl SortKey {
+    /// An ascending string key with nulls last. A range of columns sorts on
+    /// each of them in turn.
+    pub fn new<C: Into<Column>>(column: C) -> Self {
+        SortKey {
+            column: column.into(),
+            order: SortOrder::default(),
+            comparison: Comparison::default(),
+            nulls: NullOrder::default(),
+        }
+    }
+
+    pub fn order(mut self, order: SortOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn comparison(mut self, comparison: Comparison) -> Self {
+        self.comparison = comparison;
+        self
+    }
+
+    pub fn nulls(mut self, nulls: NullOrder) -> Self {
+        self.nulls = nulls;
+        self
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) | (false, true) => {
+                let null_first = if a.is_empty() {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                return match self.nulls {
+                    NullOrder::First => null_first,
+                    NullOrder::Last => null_first.reverse(),
+                };
+            }
+            (false, false) => {}
+        }
+        let ordering = match self.comparison {
+            Comparison::String => a.cmp(b),
+            Comparison::Numeric => return self.compare_parsed(a, b, parse_number),
+            Comparison::Date => return self.compare_parsed(a, b, parse_date),
+            Comparison::Natural => compare_natural(a, b),
+        };
+        self.directed(ordering)
+    }
+
+    /// Like [`compare_parsed`], but only values that both parse, or both do
+    /// not, follow the direction of the key.
+    fn compare_parsed<T: PartialOrd, F: Fn(&str) -> Option<T>>(
+        &self,
+        a: &str,
+        b: &str,
+        parse: F,
+    ) -> Ordering {
+        match (parse(a), parse(b)) {
+            (Some(x), Some(y)) => self.directed(x.partial_cmp(&y).unwrap_or(Ordering::Equal)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => self.directed(a.cmp(b)),
+        }
+    }
+
+    fn directed(&self, ordering: Ordering) -> Ordering {
+        match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+/// Sort keys resolved to column positions, comparing whole rows.
+#[derive(Debug, Clone)]
+pub(crate) struct RowComparator {
+    keys: Vec<(usize, SortKey)>,
+}
+
+impl RowComparator {
+    /// Resolves every key with `resolve`, which maps a column to the
+    /// positions it stands for.
+    pub(crate) fn new<F>(keys: &[SortKey], mut resolve: F) -> Result<Self, CsvError>
+    where
+        F: FnMut(&Column) -> Result<Vec<usize>, CsvError>,
+    {
+        let mut resolved = Vec::new();
+        for key in keys {
+            for index in resolve(&key.column)? {
+                resolved.push((index, key.clone()));
+            }
+        }
+        Ok(RowComparator { keys: resolved })
+    }
+
+    /// Compares two rows key by key. Cells past the end of a row are null.
+    pub(crate) fn compare<S: AsRef<str>>(&self, a: &[S], b: &[S]) -> Ordering {
+        self.keys
+            .iter()
+            .map(|(i, key)| key.compare(cell(a, *i), cell(b, *i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn cell<S: AsRef<str>>(row: &[S], i: usize) -> &str {
+    row.get(i).map_or("", |cell| cell.as_ref())
+}
+
+pub(crate) fn compare_parsed<T: PartialOrd, F: Fn(&str) -> Option<T>>(
+    a: &str,
+    b: &str,
+    parse: F,
+) -> Ordering {
+    match (parse(a), parse(b)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+pub(crate) fn parse_number(text: &str) -> Option<f64> {
+    text.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Year, month, day, hour, minute, second and nanosecond.
+fn parse_date(text: &str) -> Option<(u32, u32, u32, u32, u32, u32, u32)> {
+    let text = text.trim();
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = match text.find(['T', ' ']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+
+    let separator = if date.contains('/') { '/' } else { '-' };
+    let mut parts = date.split(separator);
+    let year = digits(parts.next()?, 4)?;
+    let month = digits(parts.next()?, 2).filter(|m| (1..=12).contains(m))?;
+    let day = digits(parts.next()?, 2).filter(|d| (1..=31).contains(d))?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let (mut hour, mut minute, mut second, mut nanos) = (0, 0, 0, 0);
+    if let Some(time) = time {
+        let (time, fraction) = match time.split_once('.') {
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (time, None),
+        };
+        let mut parts = time.split(':');
+        hour = digits(parts.next()?, 2).filter(|h| *h < 24)?;
+        minute = digits(parts.next()?, 2).filter(|m| *m < 60)?;
+        if let Some(s) = parts.next() {
+            second = digits(s, 2).filter(|s| *s < 61)?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        if let Some(fraction) = fraction {
+            if fraction.is_empty() || fraction.len() > 9 {
+                return None;
+            }
+            nanos = digits(fraction, fraction.len())? * 10u32.pow(9 - fraction.len() as u32);
+        }
+    }
+    Some((year, month, day, hour, minute, second, nanos))
+}
+
+/// Parses exactly `len` ASCII digits.
+fn digits(text: &str, len: usize) -> Option<u32> {
+    if text.len() != len || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+fn compare_natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    // Decides between numbers that only differ in leading zeros, like "2"
+    // and "02", once nothing else does, so that distinct strings never tie.
+    let mut zeros = Ordering::Equal;
+    loop {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return zeros,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            (false, false) => {}
+        }
+        let (a_chunk, a_rest) = split_chunk(a);
+        let (b_chunk, b_rest) = split_chunk(b);
+        let a_digits = a_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let b_digits = b_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if a_digits && b_digits {
+            let a_trimmed = a_chunk.trim_start_matches('0');
+            let b_trimmed = b_chunk.trim_start_matches('0');
+            zeros = zeros.then_with(|| a_chunk.len().cmp(&b_chunk.len()));
+            a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed))
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+        a = a_rest;
+        b = b_rest;
+    }
+}
+
+/// Splits off the leading run of digits or of non-digits.
+fn split_chunk(text: &str) -> (&str, &str) {
+    let digits = text.starts_with(|c: char| c.is_ascii_digit());
+    let end = text
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+impl CsvData {
+    /// Sorts the rows on `keys`, the first key deciding first. Rows that
+    /// compare equal on every key keep their relative order.
+    pub fn sort_by(&self, keys: &[SortKey]) -> Result<CsvData, CsvError> {
+        let comparator = RowComparator::new(keys, |column| {
+            self.column_indexes(std::slice::from_ref(column))
+        })?;
+        let mut rows: Vec<&[String]> = self.rows().collect();
+        rows.sort_by(|a, b| comparator.compare(a, b));
+        Ok(CsvData {
+            data: rows.into_iter().flatten().cloned().collect(),
+            dialect: self.dialect,
+            line_width: self.line_width,
+            headers: self.headers.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::csvdata::CsvData;
+    use crate::sort::{compare_natural, parse_date, Comparison, NullOrder, SortKey, SortOrder};
+
+    fn column(csv: &CsvData, i: usize) -> Vec<String> {
+        csv.clone().into_iter().map(|row| row[i].clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_by_keys() {
+        let csv = CsvData::from_text_with_headers(
+            "name,amount,when\nb,10,2024-01-02\na,9,\nc,10,2023-12-31T23:59\nd,,2024-01-02"
+                .to_string(),
+            ',',
+        )
+        .unwrap();
+
+        let sorted = csv.sort_by(&[SortKey::new("amount")]).unwrap();
+        assert_eq!(column(&sorted, 0), ["b", "c", "a", "d"]);
+
+        let sorted = csv
+            .sort_by(&[SortKey::new("amount")
+                .comparison(Comparison::Numeric)
+                .order(SortOrder::Descending)
+                .nulls(NullOrder::First)])
+            .unwrap();
+        // b and c tie and keep their order.
+        assert_eq!(column(&sorted, 0), ["d", "b", "c", "a"]);
+
+        // Non-numbers stay last when descending.
+        let mixed = CsvData::from_text("x\n2\n10\ny".to_string(), ',').unwrap();
+        let sorted = mixed
+            .sort_by(&[SortKey::new(0)
+                .comparison(Comparison::Numeric)
+                .order(SortOrder::Descending)])
+            .unwrap();
+        assert_eq!(column(&sorted, 0), ["10", "2", "y", "x"]);
+
+        let sorted = csv
+            .sort_by(&[
+                SortKey::new("when").comparison(Comparison::Date),
+                SortKey::new(0).order(SortOrder::Descending),
+            ])
+            .unwrap();
+        assert_eq!(column(&sorted, 0), ["c", "d", "b", "a"]);
+        assert_eq!(sorted.headers(), csv.headers());
+    }
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["file10", "file2", "File1", "file02", "file", "file2b"];
+        names.sort_by(|a, b| compare_natural(a, b));
+        assert_eq!(
+            names,
+            ["File1", "file", "file2", "file02", "file2b", "file10"]
+        );
+        assert_eq!(compare_natural("a1b", "a1b"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2024-02-29"), Some((2024, 2, 29, 0, 0, 0, 0)));
+        assert_eq!(
+            parse_date("2024/02/29 13:05:09.5Z"),
+            Some((2024, 2, 29, 13, 5, 9, 500_000_000))
+        );
+        for bad in ["2024-13-01", "24-01-01", "2024-01-01T25:00", "yesterday"] {
+            assert_eq!(parse_date(bad), None, "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Set operations over inputs that are already sorted, computed in a single
+//! k-way merge that holds one row per input in memory.
+//!
+//! The inputs are any iterators of rows, such as [`CsvReader`](crate::CsvReader)s
+//! with their header row already taken off, sorted cell by cell like the
+//! default output of the in-memory set operations. Unlike those, rows are
+//! compared as they are read, without padding them to a common width.
+
+use crate::error::CsvError;
+use crate::setops::Semantics;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// One input of the merge and the row it is positioned on.
+struct Input<I> {
+    rows: I,
+    head: Option<Vec<String>>,
+    /// Number of rows taken from `rows` so far.
+    line: usize,
+}
+
+impl<I> Input<I>
+where
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    /// Moves to the next row, failing if it sorts before the current one.
+    fn advance(&mut self) -> Result<(), CsvError> {
+        let next = self.rows.next().transpose()?;
+        self.line += 1;
+        if let (Some(next), Some(head)) = (&next, &self.head) {
+            if next < head {
+                return Err(CsvError::Unsorted { line: self.line });
+            }
+        }
+        self.head = next;
+        Ok(())
+    }
+}
+
+/// The rows of a streaming set operation, in sorted order. Yields an error
+/// and stops when an input fails or turns out not to be sorted; the
+/// [`CsvError::Unsorted`] line then counts the rows that input yielded.
+pub struct SortedMerge<I> {
+    inputs: Vec<Input<I>>,
+    operation: Operation,
+    semantics: Semantics,
+    started: bool,
+    done: bool,
+    /// Row being output and the number of copies still due.
+    pending: Option<(Vec<String>, usize)>,
+}
+
+impl<I> SortedMerge<I>
+where
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    fn new<T>(inputs: T, operation: Operation, semantics: Semantics) -> Self
+    where
+        T: IntoIterator<Item = I>,
+    {
+        SortedMerge {
+            inputs: inputs
+                .into_iter()
+                .map(|rows| Input {
+                    rows,
+                    head: None,
+                    line: 0,
+                })
+                .collect(),
+            operation,
+            semantics,
+            started: false,
+            done: false,
+            pending: None,
+        }
+    }
+
+    /// The smallest row on offer and how many copies of it to output, or
+    /// `None` once every input is exhausted.
+    fn next_group(&mut self) -> Result<Option<(Vec<String>, usize)>, CsvError> {
+        if !self.started {
+            self.started = true;
+            for input in &mut self.inputs {
+                input.advance()?;
+            }
+        }
+        loop {
+            let Some(row) = self.inputs.iter().filter_map(|i| i.head.as_ref()).min() else {
+                return Ok(None);
+            };
+            let row = row.clone();
+            let mut counts = Vec::with_capacity(self.inputs.len());
+            for input in &mut self.inputs {
+                let mut count = 0;
+                while input.head.as_ref() == Some(&row) {
+                    count += 1;
+                    input.advance()?;
+                }
+                counts.push(count);
+            }
+
+            let holders = counts.iter().filter(|&&count| count > 0).count();
+            let copies = match self.operation {
+                Operation::Union => counts.iter().sum(),
+                Operation::Intersection if holders == counts.len() => {
+                    counts.iter().copied().min().unwrap_or(0)
+                }
+                Operation::Difference if holders == 1 => counts.iter().sum(),
+                _ => 0,
+            };
+            let copies = match self.semantics {
+                Semantics::Bag => copies,
+                Semantics::Distinct => copies.min(1),
+            };
+            if copies > 0 {
+                return Ok(Some((row, copies)));
+            }
+        }
+    }
+}
+
+impl<I> Iterator for SortedMerge<I>
+where
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.pending.is_none() {
+            match self.next_group() {
+                Ok(group) => self.pending = group,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let (row, copies) = self.pending.as_mut()?;
+        *copies -= 1;
+        if *copies == 0 {
+            self.pending.take().map(|(row, _)| Ok(row))
+        } else {
+            Some(Ok(row.clone()))
+        }
+    }
+}
+
+/// Every row of every input, like `union_all_with` with the default
+/// options, merged instead of sorted.
+pub fn union_all_sorted<T, I>(inputs: T) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    union_all_sorted_with(inputs, Semantics::Bag)
+}
+
+pub fn union_all_sorted_with<T, I>(inputs: T, semantics: Semantics) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    SortedMerge::new(inputs, Operation::Union, semantics)
+}
+
+/// Rows present in every input, like `intersection_all`.
+pub fn intersection_all_sorted<T, I>(inputs: T) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    intersection_all_sorted_with(inputs, Semantics::Bag)
+}
+
+pub fn intersection_all_sorted_with<T, I>(inputs: T, semantics: Semantics) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    SortedMerge::new(inputs, Operation::Intersection, semantics)
+}
+
+/// Rows present in exactly one input, like `difference_all`.
+pub fn difference_all_sorted<T, I>(inputs: T) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    difference_all_sorted_with(inputs, Semantics::Bag)
+}
+
+pub fn difference_all_sorted_with<T, I>(inputs: T, semantics: Semantics) -> SortedMerge<I>
+where
+    T: IntoIterator<Item = I>,
+    I: Iterator<Item = Result<Vec<String>, CsvError>>,
+{
+    SortedMerge::new(inputs, Operation::Difference, semantics)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csvdata::{difference_all, intersection_all, CsvData};
+    use crate::error::CsvError;
+    use crate::reader::CsvReader;
+    use crate::setops::{Semantics, SetOptions};
+    use crate::streaming::{
+        difference_all_sorted, intersection_all_sorted, union_all_sorted, union_all_sorted_with,
+    };
+
+    const INPUTS: [&str; 3] = ["a\na\nb\nd\n", "a\nb\nb\nc\nd\n", "a\na\nd\ne\n"];
+
+    fn readers() -> Vec<CsvReader<&'static [u8]>> {
+        INPUTS
+            .iter()
+            .map(|text| CsvReader::new(text.as_bytes(), ','))
+            .collect()
+    }
+
+    fn tables() -> Vec<CsvData> {
+        INPUTS
+            .iter()
+            .map(|text| CsvData::from_text(text.trim_end().to_string(), ',').unwrap())
+            .collect()
+    }
+
+    fn cells<I: Iterator<Item = Result<Vec<String>, CsvError>>>(rows: I) -> Vec<String> {
+        rows.map(|row| row.unwrap().join(",")).collect()
+    }
+
+    #[test]
+    fn test_streaming_matches_in_memory() {
+        let csvs = tables();
+        let union = csvs[1..].iter().fold(csvs[0].clone(), |acc, csv| {
+            acc.union_with(csv, &SetOptions::default()).unwrap()
+        });
+        assert_eq!(cells(union_all_sorted(readers())), union.data);
+        assert_eq!(
+            cells(intersection_all_sorted(readers())),
+            intersection_all(&csvs).unwrap().data
+        );
+        assert_eq!(
+            cells(difference_all_sorted(readers())),
+            difference_all(&csvs).unwrap().data
+        );
+        assert_eq!(
+            cells(union_all_sorted_with(readers(), Semantics::Distinct)),
+            ["a", "b", "c", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn test_streaming_rejects_unsorted_input() {
+        let inputs = vec![
+            CsvReader::new("c\n".as_bytes(), ','),
+            CsvReader::new("a\nc\nb\n".as_bytes(), ','),
+        ];
+        let mut merge = union_all_sorted(inputs);
+        assert_eq!(merge.next().unwrap().unwrap(), ["a"]);
+        assert!(matches!(
+            merge.next(),
+            Some(Err(CsvError::Unsorted { line: 3 }))
+        ));
+        assert!(merge.next().is_none());
+    }
+}
@@ -0,0 +1,374 @@
+//! RFC 4180 record tokenizer shared by every `CsvData` constructor.
+//!
+//! The tokenizer is fed one character at a time so it can be driven both from
+//! an in-memory string and from a streaming source. A record is completed by a
+//! line break outside of quotes, whether `\n`, `\r\n` or a lone `\r`, so
+//! files with mixed line endings read fine; inside quotes delimiters and line
+//! breaks are kept verbatim and a doubled quote is unescaped to a single one. A quoted field
+//! that is never closed, or text that follows a closing quote, is reported as
+//! a [`CsvError::Parse`] pointing at the offending character. The quote,
+//! escape and comment characters come from the [`Dialect`].
+
+use crate::dialect::{Dialect, LineTerminator};
+use crate::error::CsvError;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum State {
+    StartField,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+    EscapeInQuoted,
+    Comment,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RecordParser {
+    delimiter: char,
+    quote: char,
+    escape: Option<char>,
+    comment: Option<char>,
+    skip_blank: bool,
+    state: State,
+    field: String,
+    record: Vec<String>,
+    blank: bool,
+    line: usize,
+    column: usize,
+    quote_start: (usize, usize),
+    /// Set after a `\r` that ended a line, so that a `\n` right after it is
+    /// taken as part of the same line break.
+    after_cr: bool,
+    prev_cr: bool,
+    terminator: Option<LineTerminator>,
+}
+
+impl RecordParser {
+    pub(crate) fn new(dialect: &Dialect) -> Self {
+        RecordParser {
+            delimiter: dialect.delimiter,
+            quote: dialect.quote,
+            // Escaping the quote with itself is what doubling already does.
+            escape: dialect.escape.filter(|&escape| escape != dialect.quote),
+            comment: dialect.comment,
+            skip_blank: false,
+            state: State::StartField,
+            field: String::new(),
+            record: Vec::new(),
+            blank: true,
+            line: 1,
+            column: 0,
+            quote_start: (0, 0),
+            after_cr: false,
+            prev_cr: false,
+            terminator: None,
+        }
+    }
+
+    /// Drops lines that hold no characters at all instead of yielding them as
+    /// a record with one empty field. A quoted empty field is still a record.
+    pub(crate) fn skip_blank_lines(mut self) -> Self {
+        self.skip_blank = true;
+        self
+    }
+
+    /// Feeds a single character, returning a record once one is complete.
+    pub(crate) fn feed(&mut self, c: char) -> Result<Option<Vec<String>>, CsvError> {
+        if std::mem::take(&mut self.after_cr) {
+            if c == '\n' {
+                self.prev_cr = false;
+                self.terminator.get_or_insert(LineTerminator::CrLf);
+                return Ok(None);
+            }
+            self.terminator.get_or_insert(LineTerminator::Cr);
+        }
+        // A `\n` inside quotes that follows a `\r` is the same line break.
+        let line_break = c == '\r' || (c == '\n' && !self.prev_cr);
+        self.prev_cr = c == '\r';
+        self.column += 1;
+        let result = self.step(c);
+        if line_break {
+            self.line += 1;
+            self.column = 0;
+        } else if c == '\n' {
+            self.column = 0;
+        } else {
+            self.blank = false;
+        }
+        result
+    }
+
+    fn step(&mut self, c: char) -> Result<Option<Vec<String>>, CsvError> {
+        match self.state {
+            State::StartField => {
+                if self.blank && self.record.is_empty() && Some(c) == self.comment {
+                    self.state = State::Comment;
+                } else if c == self.quote {
+                    self.quote_start = (self.line, self.column);
+                    self.state = State::Quoted;
+                } else if c == self.delimiter {
+                    self.end_field();
+                } else if is_line_break(c) {
+                    return Ok(self.end_line(c));
+                } else {
+                    self.field.push(c);
+                    self.state = State::Unquoted;
+                }
+            }
+            State::Unquoted => {
+                if c == self.delimiter {
+                    self.end_field();
+                } else if is_line_break(c) {
+                    return Ok(self.end_line(c));
+                } else {
+                    self.field.push(c);
+                }
+            }
+            State::Quoted => {
+                if c == self.quote {
+                    self.state = State::QuoteInQuoted;
+                } else if Some(c) == self.escape {
+                    self.state = State::EscapeInQuoted;
+                } else {
+                    self.field.push(c);
+                }
+            }
+            State::EscapeInQuoted => {
+                self.field.push(c);
+                self.state = State::Quoted;
+            }
+            State::Comment => {
+                if is_line_break(c) {
+                    self.end_line_break(c);
+                    self.state = State::StartField;
+                    self.blank = true;
+                }
+            }
+            State::QuoteInQuoted => {
+                if c == self.quote {
+                    self.field.push(c);
+                    self.state = State::Quoted;
+                } else if c == self.delimiter {
+                    self.end_field();
+                } else if is_line_break(c) {
+                    return Ok(self.end_line(c));
+                } else {
+                    return Err(CsvError::Parse {
+                        line: self.line,
+                        column: self.column,
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The first line break that ended a record, if one has been seen.
+    pub(crate) fn terminator(&self) -> Option<LineTerminator> {
+        self.terminator
+    }
+
+    /// Flushes the record that is still pending at the end of the input.
+    pub(crate) fn finish(&mut self) -> Result<Option<Vec<String>>, CsvError> {
+        if std::mem::take(&mut self.after_cr) {
+            self.terminator.get_or_insert(LineTerminator::Cr);
+        }
+        match self.state {
+            State::Quoted | State::EscapeInQuoted => {
+                let (line, column) = self.quote_start;
+                return Err(CsvError::Parse { line, column });
+            }
+            State::Comment => {
+                self.state = State::StartField;
+                return Ok(None);
+            }
+            _ => {}
+        }
+        Ok(self.end_record())
+    }
+
+    fn end_field(&mut self) {
+        self.record.push(std::mem::take(&mut self.field));
+        self.state = State::StartField;
+    }
+
+    /// Notes the line break `c` outside of quotes. Whether a `\r` starts a
+    /// `\r\n` is only known once the next character is fed.
+    fn end_line_break(&mut self, c: char) {
+        if c == '\r' {
+            self.after_cr = true;
+        } else {
+            self.terminator.get_or_insert(LineTerminator::Lf);
+        }
+    }
+
+    fn end_line(&mut self, c: char) -> Option<Vec<String>> {
+        self.end_line_break(c);
+        self.end_record()
+    }
+
+    fn end_record(&mut self) -> Option<Vec<String>> {
+        self.end_field();
+        let record = std::mem::take(&mut self.record);
+        let blank = std::mem::replace(&mut self.blank, true);
+        if blank && self.skip_blank {
+            return None;
+        }
+        Some(record)
+    }
+}
+
+fn is_line_break(c: char) -> bool {
+    c == '\n' || c == '\r'
+}
+
+/// Splits `text` into records of fields. Always yields at least one record,
+/// so the empty string is a single record holding one empty field.
+pub(crate) fn parse_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, CsvError> {
+    collect_records(&mut RecordParser::new(&Dialect::new(delimiter)), text)
+}
+
+pub(crate) fn collect_records(
+    parser: &mut RecordParser,
+    text: &str,
+) -> Result<Vec<Vec<String>>, CsvError> {
+    let mut records = Vec::new();
+    for c in text.chars() {
+        records.extend(parser.feed(c)?);
+    }
+    records.extend(parser.finish()?);
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::{Dialect, LineTerminator};
+    use crate::error::CsvError;
+    use crate::tokenizer::{collect_records, parse_records, RecordParser};
+
+    fn records(text: &str) -> Vec<Vec<String>> {
+        parse_records(text, ',').unwrap()
+    }
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(records("a,b\nc,d"), vec![vec!["a", "b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(records(""), vec![vec![""]]);
+    }
+
+    #[test]
+    fn test_quoted_delimiter() {
+        assert_eq!(
+            records("\"Smith, John\",42"),
+            vec![vec!["Smith, John", "42"]]
+        );
+    }
+
+    #[test]
+    fn test_escaped_quote() {
+        assert_eq!(
+            records("\"say \"\"hi\"\"\",x"),
+            vec![vec!["say \"hi\"", "x"]]
+        );
+    }
+
+    #[test]
+    fn test_embedded_newline() {
+        assert_eq!(
+            records("\"line1\nline2\",b\nc,d"),
+            vec![vec!["line1\nline2", "b"], vec!["c", "d"]]
+        );
+    }
+
+    #[test]
+    fn test_empty_quoted() {
+        assert_eq!(records("\"\",,\"\""), vec![vec!["", "", ""]]);
+    }
+
+    #[test]
+    fn test_quote_inside_unquoted() {
+        assert_eq!(records("ab\"c,d"), vec![vec!["ab\"c", "d"]]);
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        assert_eq!(records("a,b\n"), vec![vec!["a", "b"], vec![""]]);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let text = "a,b\r\nc,\"d\r\ne\"\rf,g\n\r\nh";
+        let mut parser = RecordParser::new(&Dialect::default()).skip_blank_lines();
+        assert_eq!(
+            collect_records(&mut parser, text).unwrap(),
+            vec![
+                vec!["a", "b"],
+                vec!["c", "d\r\ne"],
+                vec!["f", "g"],
+                vec!["h"]
+            ]
+        );
+        assert_eq!(parser.terminator(), Some(LineTerminator::CrLf));
+
+        assert_eq!(records("a\rb\r"), vec![vec!["a"], vec!["b"], vec![""]]);
+        match parse_records("a\r\n\"b\r\nc\"d", ',') {
+            Err(CsvError::Parse { line, column }) => assert_eq!((line, column), (3, 3)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_other_delimiter() {
+        assert_eq!(
+            parse_records("a;\"b;c\"", ';').unwrap(),
+            vec![vec!["a", "b;c"]]
+        );
+    }
+
+    #[test]
+    fn test_escape_and_comment() {
+        let dialect = Dialect {
+            quote: '\'',
+            escape: Some('\\'),
+            comment: Some('#'),
+            ..Dialect::new(';')
+        };
+        let text = "# a comment; 'not' a record\n'it\\'s';'a\\\\b';''''\n c#d;#\n#last";
+        assert_eq!(
+            collect_records(&mut RecordParser::new(&dialect), text).unwrap(),
+            vec![vec!["it's", "a\\b", "'"], vec![" c#d", "#"]]
+        );
+    }
+
+    #[test]
+    fn test_skip_blank_lines() {
+        assert_eq!(
+            collect_records(
+                &mut RecordParser::new(&Dialect::default()).skip_blank_lines(),
+                "a\n\n\"\"\nb\n"
+            )
+            .unwrap(),
+            vec![vec!["a"], vec![""], vec!["b"]]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        match parse_records("a,b\nc,\"d\ne", ',') {
+            Err(CsvError::Parse { line, column }) => assert_eq!((line, column), (2, 3)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_text_after_closing_quote() {
+        match parse_records("\"ab\"c,d", ',') {
+            Err(CsvError::Parse { line, column }) => assert_eq!((line, column), (1, 5)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Quoting-aware record formatting, the counterpart of the tokenizer.
+//!
+//! Anything written with a quoting policy other than [`QuoteStyle::Never`]
+//! reads back through `CsvData::from_file` / `from_text` to the same cells.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::dialect::{Dialect, LineTerminator};
+use crate::encoding::Encoding;
+use crate::error::CsvError;
+
+/// When cells are wrapped in quotes on output.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum QuoteStyle {
+    /// Quote only cells that contain the delimiter, a quote or a line break.
+    #[default]
+    Minimal,
+    /// Quote every cell.
+    Always,
+    /// Quote every cell that is not a finite number.
+    NonNumeric,
+    /// Never quote. The output may not read back correctly.
+    Never,
+}
+
+/// Writes records incrementally to any [`Write`] target such as a file,
+/// stdout, a socket or an in-memory buffer. Output is buffered; call
+/// [`CsvWriter::flush`] or [`CsvWriter::into_inner`] to make sure everything
+/// reached the underlying writer.
+pub struct CsvWriter<W: Write> {
+    writer: BufWriter<W>,
+    dialect: Dialect,
+    quote_style: QuoteStyle,
+    encoding: Encoding,
+    /// Whether a byte order mark still has to be written before the first
+    /// record.
+    bom: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, delimiter: char) -> Self {
+        WriterBuilder::new().delimiter(delimiter).build(writer)
+    }
+
+    pub fn with_quote_style(writer: W, delimiter: char, quote_style: QuoteStyle) -> Self {
+        WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(quote_style)
+            .build(writer)
+    }
+
+    pub fn write_record<S: AsRef<str>>(&mut self, record: &[S]) -> Result<(), CsvError> {
+        let mut line = format_record(record, &self.dialect, self.quote_style);
+        line.push_str(self.dialect.terminator.as_str());
+        let bytes = match self.encoding {
+            Encoding::Utf8 => line.into_bytes(),
+            encoding => encoding.encode(&line)?,
+        };
+        if std::mem::take(&mut self.bom) {
+            self.writer.write_all(self.encoding.bom())?;
+        }
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), CsvError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flushes the buffer and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W, CsvError> {
+        self.writer
+            .into_inner()
+            .map_err(|e| CsvError::Io(e.into_error()))
+    }
+}
+
+impl CsvWriter<File> {
+    pub fn from_path<P: AsRef<Path>>(path: P, delimiter: char) -> Result<Self, CsvError> {
+        WriterBuilder::new().delimiter(delimiter).create(path)
+    }
+}
+
+/// Sets up a [`CsvWriter`] for any [`Dialect`] and [`Encoding`]. The header
+/// flag and the trim mode of the dialect only matter on read and are
+/// ignored.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WriterBuilder {
+    dialect: Dialect,
+    quote_style: QuoteStyle,
+    encoding: Encoding,
+    bom: bool,
+}
+
+impl WriterBuilder {
+    /// A builder for the default dialect with minimal quoting.
+    pub fn new() -> Self {
+        WriterBuilder::default()
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.dialect.delimiter = delimiter;
+        self
+    }
+
+    pub fn quote(mut self, quote: char) -> Self {
+        self.dialect.quote = quote;
+        self
+    }
+
+    /// Escapes quotes inside quoted cells with `escape` instead of doubling
+    /// them.
+    pub fn escape(mut self, escape: Option<char>) -> Self {
+        self.dialect.escape = escape;
+        self
+    }
+
+    pub fn terminator(mut self, terminator: LineTerminator) -> Self {
+        self.dialect.terminator = terminator;
+        self
+    }
+
+    /// Quotes a first cell starting with `comment`, so that its record is
+    /// not read back as a comment.
+    pub fn comment(mut self, comment: Option<char>) -> Self {
+        self.dialect.comment = comment;
+        self
+    }
+
+    pub fn quote_style(mut self, quote_style: QuoteStyle) -> Self {
+        self.quote_style = quote_style;
+        self
+    }
+
+    /// Writes in `encoding` instead of UTF-8. A record holding a character
+    /// the encoding cannot represent fails with
+    /// [`CsvError::Unencodable`] and is not written.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Starts the output with the byte order mark of the encoding, which
+    /// Excel needs to tell UTF-8 from its local code page. Single-byte
+    /// encodings have none, and nothing is written for an empty output.
+    pub fn bom(mut self, bom: bool) -> Self {
+        self.bom = bom;
+        self
+    }
+
+    pub fn build<W: Write>(&self, writer: W) -> CsvWriter<W> {
+        CsvWriter {
+            writer: BufWriter::new(writer),
+            dialect: self.dialect,
+            quote_style: self.quote_style,
+            encoding: self.encoding,
+            bom: self.bom,
+        }
+    }
+
+    /// Creates or truncates the file at `path` and writes to it.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> Result<CsvWriter<File>, CsvError> {
+        Ok(self.build(File::create(path)?))
+    }
+}
+
+/// Formats one record as a line, without the trailing line terminator.
+pub(crate) fn format_record<S: AsRef<str>>(
+    record: &[S],
+    dialect: &Dialect,
+    quote_style: QuoteStyle,
+) -> String {
+    let quote = dialect.quote;
+    // A lone empty cell would otherwise produce a blank line, which readers skip.
+    if record.len() == 1 && record[0].as_ref().is_empty() && quote_style != QuoteStyle::Never {
+        return format!("{}{}", quote, quote);
+    }
+
+    let mut line = String::new();
+    for (i, cell) in record.iter().enumerate() {
+        if i > 0 {
+            line.push(dialect.delimiter);
+        }
+        let cell = cell.as_ref();
+        let commented = i == 0 && dialect.comment.is_some_and(|c| cell.starts_with(c));
+        if (commented && quote_style != QuoteStyle::Never)
+            || needs_quotes(cell, dialect, quote_style)
+        {
+            line.push(quote);
+            for c in cell.chars() {
+                if c == quote {
+                    line.push(dialect.escape.unwrap_or(quote));
+                } else if Some(c) == dialect.escape {
+                    line.push(c);
+                }
+                line.push(c);
+            }
+            line.push(quote);
+        } else {
+            line.push_str(cell);
+        }
+    }
+    line
+}
+
+fn needs_quotes(cell: &str, dialect: &Dialect, quote_style: QuoteStyle) -> bool {
+    match quote_style {
+        QuoteStyle::Always => true,
+        QuoteStyle::Never => false,
+        QuoteStyle::NonNumeric => !is_number(cell) || has_special(cell, dialect),
+        QuoteStyle::Minimal => has_special(cell, dialect),
+    }
+}
+
+/// Whether `cell` is a finite number. `NaN` and `inf` parse as `f64` but
+/// are words to anyone reading the file back.
+fn is_number(cell: &str) -> bool {
+    cell.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+fn has_special(cell: &str, dialect: &Dialect) -> bool {
+    cell.chars()
+        .any(|c| c == dialect.delimiter || c == dialect.quote || c == '\n' || c == '\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::{Dialect, LineTerminator};
+    use crate::encoding::Encoding;
+    use crate::error::CsvError;
+    use crate::writer::{format_record, CsvWriter, QuoteStyle, WriterBuilder};
+
+    #[test]
+    fn test_minimal() {
+        let record = ["plain", "a,b", "say \"hi\"", "x\ny", ""];
+        assert_eq!(
+            format_record(&record, &Dialect::default(), QuoteStyle::Minimal),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"x\ny\","
+        );
+    }
+
+    #[test]
+    fn test_always() {
+        assert_eq!(
+            format_record(&["a", "1"], &Dialect::default(), QuoteStyle::Always),
+            "\"a\",\"1\""
+        );
+    }
+
+    #[test]
+    fn test_non_numeric() {
+        assert_eq!(
+            format_record(
+                &["a", "1.5", "-2", "", "NaN", "inf", "-inf"],
+                &Dialect::new(';'),
+                QuoteStyle::NonNumeric
+            ),
+            "\"a\";1.5;-2;\"\";\"NaN\";\"inf\";\"-inf\""
+        );
+    }
+
+    #[test]
+    fn test_never() {
+        assert_eq!(
+            format_record(&["a,b", "\""], &Dialect::default(), QuoteStyle::Never),
+            "a,b,\""
+        );
+    }
+
+    #[test]
+    fn test_single_empty_cell() {
+        assert_eq!(
+            format_record(&[""], &Dialect::default(), QuoteStyle::Minimal),
+            "\"\""
+        );
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let mut writer = CsvWriter::new(Vec::new(), ',');
+        writer.write_record(&["a", "b,c"]).unwrap();
+        writer
+            .write_record(&[String::from("1"), String::from("2")])
+            .unwrap();
+        let buf = writer.into_inner().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "a,\"b,c\"\n1,2\n");
+    }
+
+    #[test]
+    fn test_csv_writer_quote_style() {
+        let mut writer = CsvWriter::with_quote_style(Vec::new(), '\t', QuoteStyle::Always);
+        writer.write_record(&["a", "1"]).unwrap();
+        let buf = writer.into_inner().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "\"a\"\t\"1\"\n");
+    }
+
+    #[test]
+    fn test_writer_builder_dialect() {
+        let mut writer = WriterBuilder::new()
+            .delimiter(';')
+            .quote('\'')
+            .escape(Some('\\'))
+            .comment(Some('#'))
+            .terminator(LineTerminator::CrLf)
+            .build(Vec::new());
+        writer
+            .write_record(&["#tag", "it's", "a\\b;c", "#"])
+            .unwrap();
+        let buf = writer.into_inner().unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "'#tag';'it\\'s';'a\\\\b;c';#\r\n"
+        );
+    }
+
+    #[test]
+    fn test_writer_builder_encoding() {
+        let builder = WriterBuilder::new().delimiter(';').bom(true);
+        let mut writer = builder.clone().build(Vec::new());
+        writer.write_record(&["caf\u{e9}", "1"]).unwrap();
+        writer.write_record(&["b", "2"]).unwrap();
+        assert_eq!(
+            writer.into_inner().unwrap(),
+            "\u{feff}caf\u{e9};1\nb;2\n".as_bytes()
+        );
+
+        let mut writer = builder
+            .clone()
+            .encoding(Encoding::Utf16Le)
+            .build(Vec::new());
+        writer.write_record(&["\u{e9}"]).unwrap();
+        assert_eq!(writer.into_inner().unwrap(), b"\xFF\xFE\xE9\0\n\0");
+
+        let mut writer = builder.encoding(Encoding::Windows1252).build(Vec::new());
+        writer.write_record(&["\u{20ac}1"]).unwrap();
+        assert!(matches!(
+            writer.write_record(&["\u{3b1}"]),
+            Err(CsvError::Unencodable {
+                character: '\u{3b1}',
+                ..
+            })
+        ));
+        assert_eq!(writer.into_inner().unwrap(), b"\x801\n");
+    }
+}